@@ -1,82 +1,197 @@
 mod component;
 mod midi;
-
-use std::sync::mpsc;
+pub mod device;
+pub mod module;
+pub mod offline;
+mod smoothing;
 
 use component::envelope::PolyEnvelope;
 use component::analog::PolyAnalog;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{Device, FromSample, Host, SizedSample, StreamConfig, SupportedStreamConfig, I24};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use cpal::{Device, FromSample, SizedSample, StreamConfig, I24};
 use crate::audio::component::cable::Cables;
-use crate::audio::component::effects::EffectsChain;
+use crate::audio::component::effects::{self, EffectsChain};
 use crate::audio::component::filter::PolyFilter;
+use crate::audio::component::ladder::{self, PolyLadder};
 use crate::audio::component::lfo::PolyLfo;
+use crate::audio::component::noise::{self, PolyNoise};
 use crate::audio::component::{analog, envelope, filter, lfo};
 use crate::audio::component::wavetable::{self, PolyWavetable};
 
+use crate::audio::device::AudioSettings;
 use crate::audio::midi::Midi;
+use crate::audio::module::{Module, ModuleId, ModuleRegistry, ModuleSlot, PortId};
+use crate::audio::smoothing::{ParameterSmoother, DEFAULT_SMOOTHING_TIME};
+use crate::common::{ComponentVec, Consumer, Producer};
 use crate::synth::SynthMessage;
 pub use component::WaveShape;
-pub use wavetable::Wavetable;
-pub use wavetable::WAVETABLE_FRAME_LENGTH;
+pub use component::filter::FilterMode;
+pub use module::ModuleKind;
+pub use wavetable::{Interpolation, Wavetable};
+pub use wavetable::{MAX_WAVETABLE_FRAMES, WAVETABLE_FRAME_LENGTH, WAVETABLE_VARIATION_COUNT};
 
 pub const MAX_POLY_COUNT: usize = 16;
+pub const BLOCK_SIZE: usize = 64;
 const MAX_CABLES: usize = 512;
+const MAX_NORMALLED_CABLES: usize = 8;
+const MAX_MODULES: usize = 64;
+/// Replaced wavetables and modules held while the queue back to the gui thread is full
+const MAX_PENDING_DROPS: usize = 64;
+
+/// One jack of one voice over a block of samples
+pub type Block = [f32; BLOCK_SIZE];
 
-#[repr(usize)]
+/// The jacks on the panel, each one is a port of one of the panel's modules
 #[allow(dead_code)]
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum InputJack {
-    Osc1Freq = OSC1_INPUT_OFFSET + analog::FREQUENCY_INPUT,
-    Osc1Phase = OSC1_INPUT_OFFSET + analog::PHASE_INPUT,
-    Osc1Level = OSC1_INPUT_OFFSET + analog::LEVEL_INPUT,
-    Osc1Amp = OSC1_INPUT_OFFSET + analog::AMP_INPUT,
-    Osc2Freq = OSC2_INPUT_OFFSET + wavetable::FREQUENCY_INPUT,
-    Osc2Phase = OSC2_INPUT_OFFSET + wavetable::PHASE_INPUT,
-    Osc2Level = OSC2_INPUT_OFFSET + wavetable::LEVEL_INPUT,
-    Osc2Amp = OSC2_INPUT_OFFSET + wavetable::AMP_INPUT,
-    Filter1Cutoff = FILTER1_INPUT_OFFSET + filter::FREQUENCY_INPUT,
-    Filter1Value = FILTER1_INPUT_OFFSET + filter::VALUE_INPUT,
-    Filter2Cutoff = FILTER2_INPUT_OFFSET + filter::FREQUENCY_INPUT,
-    Filter2Value = FILTER2_INPUT_OFFSET + filter::VALUE_INPUT,
-    Env1Gate = ENV1_INPUT_OFFSET + envelope::GATE_INPUT,
-    Env1Vel = ENV1_INPUT_OFFSET + envelope::VELOCITY_INPUT,
-    Env1Attack = ENV1_INPUT_OFFSET + envelope::ATTACK_INPUT,
-    Env1Decay = ENV1_INPUT_OFFSET + envelope::DECAY_INPUT,
-    Env1Sustain = ENV1_INPUT_OFFSET + envelope::SUSTAIN_INPUT,
-    Env1Release = ENV1_INPUT_OFFSET + envelope::RELEASE_INPUT,
-    Env2Gate = ENV2_INPUT_OFFSET + envelope::GATE_INPUT,
-    Env2Vel = ENV2_INPUT_OFFSET + envelope::VELOCITY_INPUT,
-    Env2Attack = ENV2_INPUT_OFFSET + envelope::ATTACK_INPUT,
-    Env2Decay = ENV2_INPUT_OFFSET + envelope::DECAY_INPUT,
-    Env2Sustain = ENV2_INPUT_OFFSET + envelope::SUSTAIN_INPUT,
-    Env2Release = ENV2_INPUT_OFFSET + envelope::RELEASE_INPUT,
-    Env3Gate = ENV3_INPUT_OFFSET + envelope::GATE_INPUT,
-    Env3Vel = ENV3_INPUT_OFFSET + envelope::VELOCITY_INPUT,
-    Env3Attack = ENV3_INPUT_OFFSET + envelope::ATTACK_INPUT,
-    Env3Decay = ENV3_INPUT_OFFSET + envelope::DECAY_INPUT,
-    Env3Sustain = ENV3_INPUT_OFFSET + envelope::SUSTAIN_INPUT,
-    Env3Release = ENV3_INPUT_OFFSET + envelope::RELEASE_INPUT,
-    EffectsChain = EFFECTS_CHAIN_INPUT_OFFSET,
+    Osc1Freq,
+    Osc1Phase,
+    Osc1Level,
+    Osc1Amp,
+    Osc1PulseWidth,
+    Osc1Fm,
+    Osc2Freq,
+    Osc2Phase,
+    Osc2Level,
+    Osc2Amp,
+    Osc2Sync,
+    Osc2Fm,
+    Osc2Position,
+    Filter1Cutoff,
+    Filter1Resonance,
+    Filter1Value,
+    Filter2Cutoff,
+    Filter2Resonance,
+    Filter2Value,
+    LadderCutoff,
+    LadderResonance,
+    LadderDrive,
+    LadderValue,
+    Env1Gate,
+    Env1Vel,
+    Env1Attack,
+    Env1Decay,
+    Env1Sustain,
+    Env1Release,
+    Env2Gate,
+    Env2Vel,
+    Env2Attack,
+    Env2Decay,
+    Env2Sustain,
+    Env2Release,
+    Env3Gate,
+    Env3Vel,
+    Env3Attack,
+    Env3Decay,
+    Env3Sustain,
+    Env3Release,
+    NoiseAmp,
+    EffectsChain,
+    EffectsChainSide,
+}
+
+impl InputJack {
+    pub fn port(self) -> PortId {
+        match self {
+            Self::Osc1Freq => PortId::new(OSC1_MODULE, analog::FREQUENCY_INPUT),
+            Self::Osc1Phase => PortId::new(OSC1_MODULE, analog::PHASE_INPUT),
+            Self::Osc1Level => PortId::new(OSC1_MODULE, analog::LEVEL_INPUT),
+            Self::Osc1Amp => PortId::new(OSC1_MODULE, analog::AMP_INPUT),
+            Self::Osc1PulseWidth => PortId::new(OSC1_MODULE, analog::PULSE_WIDTH_INPUT),
+            Self::Osc1Fm => PortId::new(OSC1_MODULE, analog::FM_INPUT),
+            Self::Osc2Freq => PortId::new(OSC2_MODULE, wavetable::FREQUENCY_INPUT),
+            Self::Osc2Phase => PortId::new(OSC2_MODULE, wavetable::PHASE_INPUT),
+            Self::Osc2Level => PortId::new(OSC2_MODULE, wavetable::LEVEL_INPUT),
+            Self::Osc2Amp => PortId::new(OSC2_MODULE, wavetable::AMP_INPUT),
+            Self::Osc2Sync => PortId::new(OSC2_MODULE, wavetable::SYNC_INPUT),
+            Self::Osc2Fm => PortId::new(OSC2_MODULE, wavetable::FM_INPUT),
+            Self::Osc2Position => PortId::new(OSC2_MODULE, wavetable::POSITION_INPUT),
+            Self::Filter1Cutoff => PortId::new(FILTER1_MODULE, filter::FREQUENCY_INPUT),
+            Self::Filter1Resonance => PortId::new(FILTER1_MODULE, filter::RESONANCE_INPUT),
+            Self::Filter1Value => PortId::new(FILTER1_MODULE, filter::VALUE_INPUT),
+            Self::Filter2Cutoff => PortId::new(FILTER2_MODULE, filter::FREQUENCY_INPUT),
+            Self::Filter2Resonance => PortId::new(FILTER2_MODULE, filter::RESONANCE_INPUT),
+            Self::Filter2Value => PortId::new(FILTER2_MODULE, filter::VALUE_INPUT),
+            Self::LadderCutoff => PortId::new(LADDER_MODULE, ladder::FREQUENCY_INPUT),
+            Self::LadderResonance => PortId::new(LADDER_MODULE, ladder::RESONANCE_INPUT),
+            Self::LadderDrive => PortId::new(LADDER_MODULE, ladder::DRIVE_INPUT),
+            Self::LadderValue => PortId::new(LADDER_MODULE, ladder::VALUE_INPUT),
+            Self::Env1Gate => PortId::new(ENV1_MODULE, envelope::GATE_INPUT),
+            Self::Env1Vel => PortId::new(ENV1_MODULE, envelope::VELOCITY_INPUT),
+            Self::Env1Attack => PortId::new(ENV1_MODULE, envelope::ATTACK_INPUT),
+            Self::Env1Decay => PortId::new(ENV1_MODULE, envelope::DECAY_INPUT),
+            Self::Env1Sustain => PortId::new(ENV1_MODULE, envelope::SUSTAIN_INPUT),
+            Self::Env1Release => PortId::new(ENV1_MODULE, envelope::RELEASE_INPUT),
+            Self::Env2Gate => PortId::new(ENV2_MODULE, envelope::GATE_INPUT),
+            Self::Env2Vel => PortId::new(ENV2_MODULE, envelope::VELOCITY_INPUT),
+            Self::Env2Attack => PortId::new(ENV2_MODULE, envelope::ATTACK_INPUT),
+            Self::Env2Decay => PortId::new(ENV2_MODULE, envelope::DECAY_INPUT),
+            Self::Env2Sustain => PortId::new(ENV2_MODULE, envelope::SUSTAIN_INPUT),
+            Self::Env2Release => PortId::new(ENV2_MODULE, envelope::RELEASE_INPUT),
+            Self::Env3Gate => PortId::new(ENV3_MODULE, envelope::GATE_INPUT),
+            Self::Env3Vel => PortId::new(ENV3_MODULE, envelope::VELOCITY_INPUT),
+            Self::Env3Attack => PortId::new(ENV3_MODULE, envelope::ATTACK_INPUT),
+            Self::Env3Decay => PortId::new(ENV3_MODULE, envelope::DECAY_INPUT),
+            Self::Env3Sustain => PortId::new(ENV3_MODULE, envelope::SUSTAIN_INPUT),
+            Self::Env3Release => PortId::new(ENV3_MODULE, envelope::RELEASE_INPUT),
+            Self::NoiseAmp => PortId::new(NOISE_MODULE, noise::AMP_INPUT),
+            Self::EffectsChain => PortId::new(EFFECTS_CHAIN_MODULE, effects::VALUE_INPUT),
+            Self::EffectsChainSide => PortId::new(EFFECTS_CHAIN_MODULE, effects::SIDE_INPUT),
+        }
+    }
 }
 
-#[repr(usize)]
 #[allow(dead_code)]
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum OutputJack {
-    MidiDC = MIDI_OUTPUT_OFFSET + midi::DC_OUTPUT,
-    MidiGate = MIDI_OUTPUT_OFFSET + midi::GATE_OUTPUT,
-    MidiNote = MIDI_OUTPUT_OFFSET + midi::NOTE_OUTPUT,
-    MidiVelocity = MIDI_OUTPUT_OFFSET + midi::VELOCITY_OUTPUT,
-    Osc1Value = OSC1_OUTPUT_OFFSET + analog::OUT_VALUE,
-    Osc2Value = OSC2_OUTPUT_OFFSET + wavetable::OUT_VALUE,
-    Filter1Value = FILTER1_OUTPUT_OFFSET + filter::VALUE_OUTPUT,
-    Filter2Value = FILTER2_OUTPUT_OFFSET + filter::VALUE_OUTPUT,
-    Env1Value = ENV1_OUTPUT_OFFSET + envelope::OUT_VALUE,
-    Env2Value = ENV2_OUTPUT_OFFSET + envelope::OUT_VALUE,
-    Env3Value = ENV3_OUTPUT_OFFSET + envelope::OUT_VALUE,
-    Lfo1Value = LFO1_OUTPUT_OFFSET + lfo::OUT_VALUE,
-    Lfo2Value = LFO2_OUTPUT_OFFSET + lfo::OUT_VALUE,
+    MidiDC,
+    MidiGate,
+    MidiNote,
+    MidiVelocity,
+    Osc1Value,
+    Osc1Sync,
+    Osc1Side,
+    Osc2Value,
+    Osc2Side,
+    Filter1Value,
+    Filter2Value,
+    LadderValue,
+    Env1Value,
+    Env2Value,
+    Env3Value,
+    Lfo1Value,
+    Lfo2Value,
+    NoiseWhite,
+    NoisePink,
+    NoiseBrown,
+}
+
+impl OutputJack {
+    pub fn port(self) -> PortId {
+        match self {
+            Self::MidiDC => PortId::new(MIDI_MODULE, midi::DC_OUTPUT),
+            Self::MidiGate => PortId::new(MIDI_MODULE, midi::GATE_OUTPUT),
+            Self::MidiNote => PortId::new(MIDI_MODULE, midi::NOTE_OUTPUT),
+            Self::MidiVelocity => PortId::new(MIDI_MODULE, midi::VELOCITY_OUTPUT),
+            Self::Osc1Value => PortId::new(OSC1_MODULE, analog::OUT_VALUE),
+            Self::Osc1Sync => PortId::new(OSC1_MODULE, analog::SYNC_OUTPUT),
+            Self::Osc1Side => PortId::new(OSC1_MODULE, analog::SIDE_OUTPUT),
+            Self::Osc2Value => PortId::new(OSC2_MODULE, wavetable::OUT_VALUE),
+            Self::Osc2Side => PortId::new(OSC2_MODULE, wavetable::SIDE_OUTPUT),
+            Self::Filter1Value => PortId::new(FILTER1_MODULE, filter::VALUE_OUTPUT),
+            Self::Filter2Value => PortId::new(FILTER2_MODULE, filter::VALUE_OUTPUT),
+            Self::LadderValue => PortId::new(LADDER_MODULE, ladder::VALUE_OUTPUT),
+            Self::Env1Value => PortId::new(ENV1_MODULE, envelope::OUT_VALUE),
+            Self::Env2Value => PortId::new(ENV2_MODULE, envelope::OUT_VALUE),
+            Self::Env3Value => PortId::new(ENV3_MODULE, envelope::OUT_VALUE),
+            Self::Lfo1Value => PortId::new(LFO1_MODULE, lfo::OUT_VALUE),
+            Self::Lfo2Value => PortId::new(LFO2_MODULE, lfo::OUT_VALUE),
+            Self::NoiseWhite => PortId::new(NOISE_MODULE, noise::WHITE_OUTPUT),
+            Self::NoisePink => PortId::new(NOISE_MODULE, noise::PINK_OUTPUT),
+            Self::NoiseBrown => PortId::new(NOISE_MODULE, noise::BROWN_OUTPUT),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -86,10 +201,23 @@ pub enum AudioMessage {
     Osc1Shape(WaveShape),
     Osc1Phase(f32),
     Osc1Level(f32),
+    Osc1PulseWidth(f32),
+    Osc1FmIndex(f32),
+    Osc1ThroughZero(bool),
+    Osc1Unison(f32),
+    Osc1Detune(f32),
+    Osc1Spread(f32),
     // Osc2
     Osc2Freq(f32),
     Osc2Phase(f32),
     Osc2Level(f32),
+    Osc2FmIndex(f32),
+    Osc2ThroughZero(bool),
+    Osc2Unison(f32),
+    Osc2Detune(f32),
+    Osc2Spread(f32),
+    Osc2Position(f32),
+    Osc2Interpolation(Interpolation),
     Osc2WavetableUpdate(Box<wavetable::Wavetable>),
     // Lfo1
     Lfo1Shape(WaveShape),
@@ -97,10 +225,25 @@ pub enum AudioMessage {
     // Flo2
     Lfo2Shape(WaveShape),
     Lfo2Freq(f32),
+    // Noise
+    NoiseLevel(f32),
+    NoiseShared(bool),
     // Filter1
     Filter1Freq(f32),
+    Filter1Resonance(f32),
+    Filter1Mode(FilterMode),
+    Filter1Keytrack(f32),
+    Filter1CutoffAmount(f32),
     //Filter2,
     Filter2Freq(f32),
+    Filter2Resonance(f32),
+    Filter2Mode(FilterMode),
+    Filter2Keytrack(f32),
+    Filter2CutoffAmount(f32),
+    // Ladder
+    LadderFreq(f32),
+    LadderResonance(f32),
+    LadderDrive(f32),
     // Env1
     Env1Attack(f32),
     Env1Decay(f32),
@@ -135,56 +278,55 @@ pub enum AudioMessage {
     KeyRelease(u8),
     PedalPress,
     PedalRelease,
+    // Modules, the panel has fixed modules so only scores send these for now
+    AddModule(ModuleId, ModuleSlot),
+    RemoveModule(ModuleId),
+    ModuleParameter(ModuleId, usize, f32),
     // Cables
     CableConnection(InputJack, OutputJack),
+    PortConnection(PortId, PortId),
     CableAttenuation(usize, f32),
     CableRemove(usize),
+    // Processing
+    SampleAccurateFeedback(bool),
+    ParameterSmoothing(f32),
 }
 
-pub fn init(receiver: mpsc::Receiver<AudioMessage>, sender: mpsc::Sender<SynthMessage>) -> Result<cpal::Stream, String> {
-    let stream = stream_setup(receiver, sender)?;
+/// Every producer of `AudioMessage`s gets its own queue, since the queues only allow one writer
+pub fn init(receivers: Vec<Consumer<AudioMessage>>, sender: Producer<SynthMessage>, settings: &AudioSettings) -> Result<cpal::Stream, String> {
+    let stream = stream_setup(receivers, sender, settings)?;
     stream.play().map_err(|err| format!("Error on output stream play: {err}"))?;
     Ok(stream)
 }
 
-fn stream_setup(receiver: mpsc::Receiver<AudioMessage>, sender: mpsc::Sender<SynthMessage>) -> Result<cpal::Stream, String> {
-    let (_host, device, config) = host_device_setup()?;
+fn stream_setup(receivers: Vec<Consumer<AudioMessage>>, sender: Producer<SynthMessage>, settings: &AudioSettings) -> Result<cpal::Stream, String> {
+    let (_host, device, config, stream_config) = device::host_device_setup(settings)?;
 
      match config.sample_format() {
-        cpal::SampleFormat::I8 => make_stream::<i8>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::I16 => make_stream::<i16>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::I24 => make_stream::<I24>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::I32 => make_stream::<i32>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::I64 => make_stream::<i64>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::U8 => make_stream::<u8>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::U16 => make_stream::<u16>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::U32 => make_stream::<u32>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::U64 => make_stream::<u64>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::F32 => make_stream::<f32>(receiver, sender, &device, &config.into()),
-        cpal::SampleFormat::F64 => make_stream::<f64>(receiver, sender, &device, &config.into()),
+        cpal::SampleFormat::I8 => make_stream::<i8>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::I16 => make_stream::<i16>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::I24 => make_stream::<I24>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::I32 => make_stream::<i32>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::I64 => make_stream::<i64>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::U8 => make_stream::<u8>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::U16 => make_stream::<u16>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::U32 => make_stream::<u32>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::U64 => make_stream::<u64>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::F32 => make_stream::<f32>(receivers, sender, &device, &stream_config),
+        cpal::SampleFormat::F64 => make_stream::<f64>(receivers, sender, &device, &stream_config),
         sample_format => Err(format!(
             "Unsupported sample format '{sample_format}'"
         )),
     }
 }
 
-fn host_device_setup() -> Result<(Host, Device, SupportedStreamConfig), String> {
-    let host = cpal::default_host();
-    let device = host.default_output_device().ok_or_else(|| String::from("Default output device is not available"))?;
-    let config = device
-        .default_output_config()
-        .map_err(|err| format!("Default output config is unavailable: {}", err))?;
-    Ok((host, device, config))
-}
-
-fn make_stream<T>(receiver: mpsc::Receiver<AudioMessage>, sender: mpsc::Sender<SynthMessage>, device: &Device, config: &StreamConfig) -> Result<cpal::Stream, String>
+fn make_stream<T>(receivers: Vec<Consumer<AudioMessage>>, sender: Producer<SynthMessage>, device: &Device, config: &StreamConfig) -> Result<cpal::Stream, String>
 where 
     T: SizedSample + FromSample<f32>,
 {
     let num_channels = config.channels as usize;
-    assert!(num_channels == 2);
     let sample_rate = config.sample_rate.0 as f64;
-    let mut audio_state = AudioState::new(receiver, sender, sample_rate);
+    let mut audio_state = AudioState::new(receivers, sender, sample_rate);
 
     let err_fn = |err| eprintln!("Erroring building output sound stream: {err}");
     device.build_output_stream(
@@ -192,17 +334,24 @@ where
         move |output: &mut [T], _: &cpal::OutputCallbackInfo| {
             let mut meter_level_left = 0.0;
             let mut meter_level_right = 0.0;
-            for i in 0..output.len() / 2 {
-                let (left, right) = audio_state.process();
-                let left_clamped = left.clamp(-1.0, 1.0);
-                meter_level_left += left_clamped * left_clamped;
-                let right_clamped = right.clamp(-1.0, 1.0);
-                meter_level_right += right_clamped * right_clamped;
-
-                output[i*2] = T::from_sample(left_clamped);
-                output[i*2 + 1] = T::from_sample(right_clamped);
+            let mut left = [0.0; BLOCK_SIZE];
+            let mut right = [0.0; BLOCK_SIZE];
+            for output_block in output.chunks_mut(BLOCK_SIZE * num_channels) {
+                let frames = output_block.len() / num_channels;
+                audio_state.process_block(&mut left[..frames], &mut right[..frames]);
+                for (i, frame) in output_block.chunks_exact_mut(num_channels).enumerate() {
+                    let left_clamped = left[i].clamp(-1.0, 1.0);
+                    meter_level_left += left_clamped * left_clamped;
+                    let right_clamped = right[i].clamp(-1.0, 1.0);
+                    meter_level_right += right_clamped * right_clamped;
+
+                    write_frame(frame, left_clamped, right_clamped);
+                }
             }
-            audio_state.sender.send(SynthMessage::MasterMeter(meter_level_left / output.len() as f32, meter_level_right / output.len() as f32)).unwrap();
+            // Meter levels are averaged as if the output was always stereo
+            let meter_samples = (output.len() / num_channels * 2) as f32;
+            // Dropping a meter reading is harmless if the gui has fallen behind
+            let _ = audio_state.sender.push(SynthMessage::MasterMeter(meter_level_left / meter_samples, meter_level_right / meter_samples));
             audio_state.update();
         },
         err_fn,
@@ -210,70 +359,156 @@ where
     ).map_err(|err| format!("Failed to create audio output stream: {err}"))
 }
 
-const EFFECTS_CHAIN_INPUT_OFFSET: usize = 0;
-const OSC1_INPUT_OFFSET: usize = MAX_POLY_COUNT;
-const OSC2_INPUT_OFFSET: usize = OSC1_INPUT_OFFSET + analog::TOTAL_INPUT_COUNT;
-const ENV1_INPUT_OFFSET: usize = OSC2_INPUT_OFFSET + wavetable::TOTAL_INPUT_COUNT;
-const ENV2_INPUT_OFFSET: usize = ENV1_INPUT_OFFSET + envelope::TOTAL_INPUT_COUNT;
-const ENV3_INPUT_OFFSET: usize = ENV2_INPUT_OFFSET + envelope::TOTAL_INPUT_COUNT;
-const FILTER1_INPUT_OFFSET: usize = ENV3_INPUT_OFFSET + envelope::TOTAL_INPUT_COUNT;
-const FILTER2_INPUT_OFFSET: usize = FILTER1_INPUT_OFFSET + filter::TOTAL_INPUT_COUNT;
-const LFO1_INPUT_OFFSET: usize = FILTER2_INPUT_OFFSET + filter::TOTAL_INPUT_COUNT;
-const LFO2_INPUT_OFFSET: usize = LFO1_INPUT_OFFSET + lfo::TOTAL_INPUT_COUNT;
-const TOTAL_INPUT_COUNT: usize = LFO2_INPUT_OFFSET + lfo::TOTAL_INPUT_COUNT;
-
-const MIDI_OUTPUT_OFFSET: usize = 0;
-const OSC1_OUTPUT_OFFSET: usize =  MIDI_OUTPUT_OFFSET + midi::TOTAL_OUTPUT_COUNT;
-const OSC2_OUTPUT_OFFSET: usize = OSC1_OUTPUT_OFFSET + analog::TOTAL_OUTPUT_COUNT;
-const ENV1_OUTPUT_OFFSET: usize = OSC2_OUTPUT_OFFSET + wavetable::TOTAL_OUTPUT_COUNT;
-const ENV2_OUTPUT_OFFSET: usize = ENV1_OUTPUT_OFFSET + envelope::TOTAL_OUTPUT_COUNT;
-const ENV3_OUTPUT_OFFSET: usize = ENV2_OUTPUT_OFFSET + envelope::TOTAL_OUTPUT_COUNT;
-const FILTER1_OUTPUT_OFFSET: usize = ENV3_OUTPUT_OFFSET + envelope::TOTAL_OUTPUT_COUNT;
-const FILTER2_OUTPUT_OFFSET: usize = FILTER1_OUTPUT_OFFSET + filter::TOTAL_OUTPUT_COUNT;
-const LFO1_OUTPUT_OFFSET: usize = FILTER2_OUTPUT_OFFSET + filter::TOTAL_OUTPUT_COUNT;
-const LFO2_OUTPUT_OFFSET: usize = LFO1_OUTPUT_OFFSET + lfo::TOTAL_OUTPUT_COUNT;
-const TOTAL_OUTPUT_COUNT: usize = LFO2_OUTPUT_OFFSET + lfo::TOTAL_OUTPUT_COUNT;
+/// Mono devices get a mixdown, wider devices repeat the stereo pair across their channels
+fn write_frame<T: SizedSample + FromSample<f32>>(frame: &mut [T], left: f32, right: f32) {
+    if let [mono] = frame {
+        *mono = T::from_sample((left + right) * 0.5);
+        return;
+    }
+    for (channel, sample) in frame.iter_mut().enumerate() {
+        *sample = T::from_sample(if channel % 2 == 0 { left } else { right });
+    }
+}
+
+// Modules of the panel, added in render order. The effects chain only collects cables and is never in the registry
+const EFFECTS_CHAIN_MODULE: ModuleId = 0;
+const MIDI_MODULE: ModuleId = 1;
+const LFO1_MODULE: ModuleId = 2;
+const LFO2_MODULE: ModuleId = 3;
+const ENV1_MODULE: ModuleId = 4;
+const ENV2_MODULE: ModuleId = 5;
+const ENV3_MODULE: ModuleId = 6;
+const OSC1_MODULE: ModuleId = 7;
+const OSC2_MODULE: ModuleId = 8;
+const FILTER1_MODULE: ModuleId = 9;
+const FILTER2_MODULE: ModuleId = 10;
+const NOISE_MODULE: ModuleId = 11;
+const LADDER_MODULE: ModuleId = 12;
+
+/// Side ports a cable between two value jacks carries, so the unison spread goes through the same filters as the mid
+fn side_route(modules: &ModuleRegistry, source: PortId, target: PortId) -> Option<(PortId, PortId)> {
+    let source_side = modules.get(source.module)?.side_output(source.jack)?;
+    let target_side = match target.module {
+        EFFECTS_CHAIN_MODULE if target.jack == effects::VALUE_INPUT => effects::SIDE_INPUT,
+        EFFECTS_CHAIN_MODULE => return None,
+        _ => modules.get(target.module)?.side_input(target.jack)?,
+    };
+    Some((PortId::new(source.module, source_side), PortId::new(target.module, target_side)))
+}
+
+/// A cable feeding a module that renders no later than its source only sees the previous block
+fn is_feedback_cable(modules: &ModuleRegistry, source: PortId, target: PortId) -> bool {
+    if target.module == EFFECTS_CHAIN_MODULE {
+        return false;
+    }
+    match (modules.render_position(source.module), modules.render_position(target.module)) {
+        (Some(source), Some(target)) => source >= target,
+        _ => false,
+    }
+}
+
+/// Knob positions the gui starts at, registered with the smoother so a knob's first turn ramps from there
+fn panel_defaults() -> Vec<(ModuleId, usize, f32)> {
+    let lfo_freq = (0.9f32 / 99.9).powf(1.0 / 3.0);
+    let (attack, decay, sustain, release) = (
+        (0.02f32 / 10.0).powf(1.0 / 3.0),
+        (2.6f32 / 10.0).powf(1.0 / 3.0),
+        0.0,
+        (2.0f32 / 10.0).powf(1.0 / 3.0),
+    );
+    let mut defaults = vec![
+        (OSC1_MODULE, analog::FREQUENCY_PARAMETER, 0.5),
+        (OSC1_MODULE, analog::LEVEL_PARAMETER, 0.5),
+        (OSC1_MODULE, analog::PULSE_WIDTH_PARAMETER, 0.5),
+        (OSC1_MODULE, analog::PHASE_PARAMETER, 0.0),
+        (OSC1_MODULE, analog::FM_INDEX_PARAMETER, 0.0),
+        (OSC1_MODULE, analog::UNISON_PARAMETER, 0.0),
+        (OSC1_MODULE, analog::DETUNE_PARAMETER, 0.0),
+        (OSC1_MODULE, analog::SPREAD_PARAMETER, 0.0),
+        (OSC2_MODULE, wavetable::FREQUENCY_PARAMETER, 0.5),
+        (OSC2_MODULE, wavetable::LEVEL_PARAMETER, 0.5),
+        (OSC2_MODULE, wavetable::PHASE_PARAMETER, 0.0),
+        (OSC2_MODULE, wavetable::FM_INDEX_PARAMETER, 0.0),
+        (OSC2_MODULE, wavetable::UNISON_PARAMETER, 0.0),
+        (OSC2_MODULE, wavetable::DETUNE_PARAMETER, 0.0),
+        (OSC2_MODULE, wavetable::SPREAD_PARAMETER, 0.0),
+        (OSC2_MODULE, wavetable::POSITION_PARAMETER, 0.0),
+        (LFO1_MODULE, lfo::FREQUENCY_PARAMETER, lfo_freq),
+        (LFO2_MODULE, lfo::FREQUENCY_PARAMETER, lfo_freq),
+        (NOISE_MODULE, noise::LEVEL_PARAMETER, 0.5),
+        (LADDER_MODULE, ladder::FREQUENCY_PARAMETER, 0.7),
+        (LADDER_MODULE, ladder::RESONANCE_PARAMETER, 0.0),
+        (LADDER_MODULE, ladder::DRIVE_PARAMETER, 0.0),
+        (EFFECTS_CHAIN_MODULE, effects::DIST_DRIVE_PARAMETER, 0.0),
+        (EFFECTS_CHAIN_MODULE, effects::DIST_WET_PARAMETER, 0.0),
+        (EFFECTS_CHAIN_MODULE, effects::DELAY_FEEDBACK_PARAMETER, 0.0),
+        (EFFECTS_CHAIN_MODULE, effects::DELAY_WET_PARAMETER, 0.0),
+        (EFFECTS_CHAIN_MODULE, effects::DELAY_TIME_PARAMETER, 0.5),
+        (EFFECTS_CHAIN_MODULE, effects::REVERB_DAMP_PARAMETER, 0.0),
+        (EFFECTS_CHAIN_MODULE, effects::REVERB_WET_PARAMETER, 0.0),
+        (EFFECTS_CHAIN_MODULE, effects::REVERB_SPACE_PARAMETER, 1.0),
+        (EFFECTS_CHAIN_MODULE, effects::MASTER_GAIN_PARAMETER, 0.7),
+    ];
+    for id in [FILTER1_MODULE, FILTER2_MODULE] {
+        defaults.extend([
+            (id, filter::FREQUENCY_PARAMETER, 0.7),
+            (id, filter::RESONANCE_PARAMETER, 0.0),
+            (id, filter::KEYTRACK_PARAMETER, 0.0),
+            (id, filter::CUTOFF_AMOUNT_PARAMETER, 1.0),
+        ]);
+    }
+    for id in [ENV1_MODULE, ENV2_MODULE, ENV3_MODULE] {
+        defaults.extend([
+            (id, envelope::ATTACK_PARAMETER, attack),
+            (id, envelope::DECAY_PARAMETER, decay),
+            (id, envelope::SUSTAIN_PARAMETER, sustain),
+            (id, envelope::RELEASE_PARAMETER, release),
+        ]);
+    }
+    defaults
+}
+
+fn apply_parameter(modules: &mut ModuleRegistry, effects_chain: &mut EffectsChain, module: ModuleId, parameter: usize, value: f32) {
+    if module == EFFECTS_CHAIN_MODULE {
+        effects_chain.set_parameter(parameter, value);
+    } else {
+        modules.set_parameter(module, parameter, value);
+    }
+}
 
 struct AudioState {
-    receiver: mpsc::Receiver<AudioMessage>,
-    sender: mpsc::Sender<SynthMessage>,
+    receivers: Vec<Consumer<AudioMessage>>,
+    sender: Producer<SynthMessage>,
+    /// Replaced data waiting for room in the queue back to the gui thread
+    pending_drops: ComponentVec<SynthMessage, MAX_PENDING_DROPS>,
+    /// Replaced data leaked on purpose because `pending_drops` was full
+    leaked_drops: usize,
     sample_rate: f64,
-    inputs: [f32; TOTAL_INPUT_COUNT],
-    outputs: [f32; TOTAL_OUTPUT_COUNT],
-    midi: Midi,
-    osc1: PolyAnalog<OSC1_INPUT_OFFSET, OSC1_OUTPUT_OFFSET>,
-    osc2: PolyWavetable<OSC2_INPUT_OFFSET, OSC2_OUTPUT_OFFSET>,
-    env1: PolyEnvelope<ENV1_INPUT_OFFSET, ENV1_OUTPUT_OFFSET>,
-    env2: PolyEnvelope<ENV2_INPUT_OFFSET, ENV2_OUTPUT_OFFSET>,
-    env3: PolyEnvelope<ENV3_INPUT_OFFSET, ENV3_OUTPUT_OFFSET>,
-    filter1: PolyFilter<FILTER1_INPUT_OFFSET, FILTER1_OUTPUT_OFFSET>,
-    filter2: PolyFilter<FILTER2_INPUT_OFFSET, FILTER2_OUTPUT_OFFSET>,
-    lfo1: PolyLfo<LFO1_INPUT_OFFSET, LFO1_OUTPUT_OFFSET>,
-    lfo2: PolyLfo<LFO2_INPUT_OFFSET, LFO2_OUTPUT_OFFSET>,
+    sample_accurate_feedback: bool,
+    modules: ModuleRegistry,
+    effects_inputs: Vec<Block>,
     effects_chain: EffectsChain,
     cables: Cables<MAX_CABLES>,
+    /// Fixed connections behind the panel that the gui never sees, like the MIDI note into the filters' keytrack
+    normals: Cables<MAX_NORMALLED_CABLES>,
+    smoother: ParameterSmoother,
 }
 
 impl AudioState {
-    pub fn new(receiver: mpsc::Receiver<AudioMessage>, sender: mpsc::Sender<SynthMessage>, sample_rate: f64) -> Self {
+    pub fn new(receivers: Vec<Consumer<AudioMessage>>, sender: Producer<SynthMessage>, sample_rate: f64) -> Self {
         let mut new_state = Self {
-            receiver,
+            receivers,
             sender,
+            pending_drops: ComponentVec::new(),
+            leaked_drops: 0,
             sample_rate,
-            inputs: [0.0; TOTAL_INPUT_COUNT],
-            outputs: [0.0; TOTAL_OUTPUT_COUNT],
-            midi: Midi::new(),
-            osc1: PolyAnalog::new(),
-            osc2: PolyWavetable::new(),
-            env1: PolyEnvelope::new(),
-            env2: PolyEnvelope::new(),
-            env3: PolyEnvelope::new(),
-            filter1: PolyFilter::new(),
-            filter2: PolyFilter::new(),
-            lfo1: PolyLfo::new(),
-            lfo2: PolyLfo::new(),
+            sample_accurate_feedback: true,
+            modules: ModuleRegistry::new(),
+            effects_inputs: vec![[0.0; BLOCK_SIZE]; effects::TOTAL_INPUT_COUNT],
             effects_chain: EffectsChain::new(sample_rate),
             cables: Cables::new(),
+            normals: Cables::new(),
+            smoother: ParameterSmoother::new(DEFAULT_SMOOTHING_TIME, sample_rate),
         };
 
         new_state.init();
@@ -281,96 +516,375 @@ impl AudioState {
     }
 
     pub fn init(&mut self) {
-        self.outputs[MIDI_OUTPUT_OFFSET + midi::DC_OUTPUT] = 1.0;
+        let panel: [(ModuleId, Box<dyn Module>); 12] = [
+            (MIDI_MODULE, Box::new(Midi::new())),
+            (LFO1_MODULE, Box::new(PolyLfo::new())),
+            (LFO2_MODULE, Box::new(PolyLfo::new())),
+            (ENV1_MODULE, Box::new(PolyEnvelope::new())),
+            (ENV2_MODULE, Box::new(PolyEnvelope::new())),
+            (ENV3_MODULE, Box::new(PolyEnvelope::new())),
+            (OSC1_MODULE, Box::new(PolyAnalog::new())),
+            (OSC2_MODULE, Box::new(PolyWavetable::new())),
+            (NOISE_MODULE, Box::new(PolyNoise::new())),
+            (FILTER1_MODULE, Box::new(PolyFilter::new())),
+            (FILTER2_MODULE, Box::new(PolyFilter::new())),
+            (LADDER_MODULE, Box::new(PolyLadder::new())),
+        ];
+        for (id, module) in panel {
+            self.modules.insert(id, ModuleSlot::new(module));
+        }
+
+        let normals = [
+            (PortId::new(MIDI_MODULE, midi::NOTE_OUTPUT), PortId::new(FILTER1_MODULE, filter::KEYTRACK_INPUT)),
+            (PortId::new(MIDI_MODULE, midi::NOTE_OUTPUT), PortId::new(FILTER2_MODULE, filter::KEYTRACK_INPUT)),
+        ];
+        for (source, target) in normals {
+            let feedback = is_feedback_cable(&self.modules, source, target);
+            self.normals.add_cable(source, target, None, feedback).unwrap_or_default();
+        }
+
+        for (module, parameter, value) in panel_defaults() {
+            self.smoother.register(module, parameter, value);
+        }
+    }
+}
+
+impl Drop for AudioState {
+    /// The stream is torn down off the audio thread, so what is still pending can be freed here
+    fn drop(&mut self) {
+        while let Some(garbage) = self.pending_drops.pop() {
+            drop(garbage);
+        }
+        if self.leaked_drops > 0 {
+            eprintln!("Leaked {} replaced wavetables or modules while the gui thread was not keeping up", self.leaked_drops);
+        }
     }
 }
 
 impl AudioState {
-    fn process(&mut self) -> (f32, f32) {
-        self.midi.process(&mut self.outputs);
-        self.osc1.render(&self.inputs, &mut self.outputs, self.sample_rate);
-        self.osc2.render(&self.inputs, &mut self.outputs, self.sample_rate as f32);
-        self.env1.render(&self.inputs, &mut self.outputs);
-        self.env2.render(&self.inputs, &mut self.outputs);
-        self.env3.render(&self.inputs, &mut self.outputs);
-        self.filter1.render(&self.inputs, &mut self.outputs, self.sample_rate as f32);
-        self.filter2.render(&self.inputs, &mut self.outputs, self.sample_rate as f32);
-        self.lfo1.render(&self.inputs, &mut self.outputs, self.sample_rate);
-        self.lfo2.render(&self.inputs, &mut self.outputs, self.sample_rate);
-        self.cables.run_cables(&mut self.inputs, &self.outputs);
-        self.effects_chain.render(unsafe { self.inputs[0..MAX_POLY_COUNT].try_into().unwrap_unchecked() })
+    /// Renders any number of frames, feedback cables force single sample chunks unless disabled
+    ///
+    /// Knob ramps shorten the chunks too, so a ramp advances in small steps instead of once per block.
+    fn process_block(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let chunk_size = if self.sample_accurate_feedback && self.cables.has_feedback() {
+            1
+        } else {
+            BLOCK_SIZE
+        };
+        let mut start = 0;
+        while start < left.len() {
+            let end = start + self.smoother.chunk_len(chunk_size.min(left.len() - start));
+            self.process_chunk(&mut left[start..end], &mut right[start..end]);
+            start = end;
+        }
+    }
+
+    fn process_chunk(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let len = left.len();
+        let (modules, effects_chain) = (&mut self.modules, &mut self.effects_chain);
+        self.smoother.advance(len, |module, parameter, value| {
+            apply_parameter(modules, effects_chain, module, parameter, value);
+        });
+        self.modules.render(&self.cables, &self.normals, len, self.sample_rate);
+        self.cables.run_cables(&mut self.effects_inputs, self.modules.outputs(), EFFECTS_CHAIN_MODULE, len);
+        self.effects_chain.render(&self.effects_inputs, left, right);
+        self.cables.advance(len);
+    }
+
+    /// Knob changes go through the smoother instead of straight to the module
+    fn set_parameter(&mut self, module: ModuleId, parameter: usize, value: f32) {
+        if !self.smoother.set(module, parameter, value) {
+            apply_parameter(&mut self.modules, &mut self.effects_chain, module, parameter, value);
+        }
+    }
+
+    fn connect(&mut self, source: PortId, target: PortId) {
+        let feedback = is_feedback_cable(&self.modules, source, target);
+        let side = side_route(&self.modules, source, target);
+        self.cables.add_cable(source, target, side, feedback).unwrap_or_default();
+    }
+
+    /// Reroutes the cables after a module was added, replaced or removed
+    fn update_routes(&mut self) {
+        let modules = &self.modules;
+        self.cables.update_feedback(|source, target| is_feedback_cable(modules, source, target));
+        self.cables.update_sides(|source, target| side_route(modules, source, target));
+        self.normals.update_feedback(|source, target| is_feedback_cable(modules, source, target));
     }
 
     fn update(&mut self) {
-        for msg in self.receiver.try_iter() {
-            match msg {
-                AudioMessage::Osc1Freq(freq) => self.osc1.set_freq_value(freq),
-                AudioMessage::Osc1Shape(shape) => self.osc1.set_shape(shape),
-                AudioMessage::Osc1Level(level) => self.osc1.set_level_value(level),
-                AudioMessage::Osc1Phase(phase) => self.osc1.set_phase_value(phase),
-                // Osc2
-                AudioMessage::Osc2Phase(phase) => self.osc2.set_phase_value(phase),
-                AudioMessage::Osc2Freq(freq) => self.osc2.set_freq_value(freq),
-                AudioMessage::Osc2WavetableUpdate(new_wavetable) => self.osc2.update_wavetable(new_wavetable),
-                AudioMessage::Osc2Level(level) => self.osc2.set_level_value(level),
-
-                // Lfo1
-                AudioMessage::Lfo1Freq(freq) => self.lfo1.set_freq_value(freq),
-                AudioMessage::Lfo1Shape(shape) => self.lfo1.set_shape(shape),
-                // Lfo2
-                AudioMessage::Lfo2Freq(freq) => self.lfo2.set_freq_value(freq),
-                AudioMessage::Lfo2Shape(shape) => self.lfo2.set_shape(shape),
-
-                // Filter1
-                AudioMessage::Filter1Freq(freq) => self.filter1.set_freq_value(freq),
-                // Filter2
-                AudioMessage::Filter2Freq(freq) => self.filter2.set_freq_value(freq),
-
-                // Env1
-                AudioMessage::Env1Attack(attack) => self.env1.set_attack_value(attack),
-                AudioMessage::Env1Decay(decay) => self.env1.set_decay_value(decay),
-                AudioMessage::Env1Sustain(sustain) => self.env1.set_sustain_value(sustain),
-                AudioMessage::Env1Release(release) => self. env1.set_release_value(release),
-                // Env1
-                AudioMessage::Env2Attack(attack) => self.env2.set_attack_value(attack),
-                AudioMessage::Env2Decay(decay) => self.env2.set_decay_value(decay),
-                AudioMessage::Env2Sustain(sustain) => self.env2.set_sustain_value(sustain),
-                AudioMessage::Env2Release(release) => self. env2.set_release_value(release),
-                // Env1
-                AudioMessage::Env3Attack(attack) => self.env3.set_attack_value(attack),
-                AudioMessage::Env3Decay(decay) => self.env3.set_decay_value(decay),
-                AudioMessage::Env3Sustain(sustain) => self.env3.set_sustain_value(sustain),
-                AudioMessage::Env3Release(release) => self. env3.set_release_value(release),
-
-                // Effects
-                // Distortion
-                AudioMessage::DistDrive(drive) => self.effects_chain.set_dist_drive(drive),
-                AudioMessage::DistWet(wet) => self.effects_chain.set_dist_wet(wet),
-                // Delay
-                AudioMessage::DelayWet(wet) => self.effects_chain.set_delay_wet(wet),
-                AudioMessage::DelayFeedback(feedback) => self.effects_chain.set_delay_feedback(feedback),
-                AudioMessage::DelayTime(time) => self.effects_chain.set_delay_time(time, self.sample_rate as f32),
-                // Reverb
-                AudioMessage::ReverbDamp(damp) => self.effects_chain.set_reverb_damp(damp),
-                AudioMessage::ReverbSpread(spread) => self.effects_chain.set_reverb_spread(spread),
-                AudioMessage::ReverbWet(wet) => self.effects_chain.set_reverb_wet(wet),
-                AudioMessage::ReverbSpace(space) => self.effects_chain.set_reverb_space(space),
-
-                // Master
-                AudioMessage::MasterGain(gain) => self.effects_chain.set_master_gain(gain),
-
-                // Midi
-                AudioMessage::KeyPress(velocity, note) => self.midi.key_press(&mut self.outputs, note, velocity),
-                AudioMessage::KeyRelease(note) => self.midi.key_release(&mut self.outputs, note),
-                AudioMessage::PedalPress => self.midi.pedal_press(),
-                AudioMessage::PedalRelease => self.midi.pedal_release(&mut self.outputs),
-
-                // Cables
-                AudioMessage::CableConnection(target, source) => self.cables.add_cable(source, target).unwrap(),
-                AudioMessage::CableAttenuation(cable_index, new_value) => self.cables.attenaute(cable_index, new_value),
-                AudioMessage::CableRemove(cable_index) => self.cables.remove_cable(cable_index),
+        self.flush_drops();
+        for i in 0..self.receivers.len() {
+            while let Some(msg) = self.receivers[i].pop() {
+                self.handle_message(msg);
+            }
+        }
+    }
+
+    /// Sends replaced wavetables and modules to the gui thread so they are never freed on the audio thread
+    ///
+    /// Whatever does not fit in the queue waits in `pending_drops`, once that is full too it is leaked instead.
+    fn defer_drop(&mut self, garbage: SynthMessage) {
+        self.flush_drops();
+        let garbage = if self.pending_drops.is_empty() {
+            match self.sender.push(garbage) {
+                Ok(()) => return,
+                Err(garbage) => garbage,
             }
+        } else {
+            garbage
+        };
+        if self.pending_drops.len() < MAX_PENDING_DROPS {
+            self.pending_drops.push(garbage).unwrap_or_default();
+        } else {
+            std::mem::forget(garbage);
+            self.leaked_drops += 1;
+        }
+    }
+
+    /// Retries the pending drops until the queue is full again
+    fn flush_drops(&mut self) {
+        while let Some(garbage) = self.pending_drops.pop() {
+            if let Err(garbage) = self.sender.push(garbage) {
+                // There is room, the slot was just freed
+                self.pending_drops.push(garbage).unwrap_or_default();
+                break;
+            }
+        }
+    }
+
+    fn handle_message(&mut self, msg: AudioMessage) {
+        match msg {
+            AudioMessage::Osc1Freq(freq) => self.set_parameter(OSC1_MODULE, analog::FREQUENCY_PARAMETER, freq),
+            AudioMessage::Osc1Shape(shape) => if let Some(osc1) = self.modules.get_mut::<PolyAnalog>(OSC1_MODULE) {
+                osc1.set_shape(shape);
+            },
+            AudioMessage::Osc1Level(level) => self.set_parameter(OSC1_MODULE, analog::LEVEL_PARAMETER, level),
+            AudioMessage::Osc1PulseWidth(width) => self.set_parameter(OSC1_MODULE, analog::PULSE_WIDTH_PARAMETER, width),
+            AudioMessage::Osc1Phase(phase) => self.set_parameter(OSC1_MODULE, analog::PHASE_PARAMETER, phase),
+            AudioMessage::Osc1FmIndex(index) => self.set_parameter(OSC1_MODULE, analog::FM_INDEX_PARAMETER, index),
+            AudioMessage::Osc1Unison(unison) => self.set_parameter(OSC1_MODULE, analog::UNISON_PARAMETER, unison),
+            AudioMessage::Osc1Detune(detune) => self.set_parameter(OSC1_MODULE, analog::DETUNE_PARAMETER, detune),
+            AudioMessage::Osc1Spread(spread) => self.set_parameter(OSC1_MODULE, analog::SPREAD_PARAMETER, spread),
+            AudioMessage::Osc1ThroughZero(through_zero) => if let Some(osc1) = self.modules.get_mut::<PolyAnalog>(OSC1_MODULE) {
+                osc1.set_through_zero(through_zero);
+            },
+            // Osc2
+            AudioMessage::Osc2Phase(phase) => self.set_parameter(OSC2_MODULE, wavetable::PHASE_PARAMETER, phase),
+            AudioMessage::Osc2Freq(freq) => self.set_parameter(OSC2_MODULE, wavetable::FREQUENCY_PARAMETER, freq),
+            AudioMessage::Osc2WavetableUpdate(new_wavetable) => {
+                let garbage = match self.modules.get_mut::<PolyWavetable>(OSC2_MODULE) {
+                    Some(osc2) => osc2.update_wavetable(new_wavetable),
+                    None => new_wavetable,
+                };
+                self.defer_drop(SynthMessage::DropWavetable(garbage));
+            },
+            AudioMessage::Osc2Level(level) => self.set_parameter(OSC2_MODULE, wavetable::LEVEL_PARAMETER, level),
+            AudioMessage::Osc2FmIndex(index) => self.set_parameter(OSC2_MODULE, wavetable::FM_INDEX_PARAMETER, index),
+            AudioMessage::Osc2Unison(unison) => self.set_parameter(OSC2_MODULE, wavetable::UNISON_PARAMETER, unison),
+            AudioMessage::Osc2Detune(detune) => self.set_parameter(OSC2_MODULE, wavetable::DETUNE_PARAMETER, detune),
+            AudioMessage::Osc2Spread(spread) => self.set_parameter(OSC2_MODULE, wavetable::SPREAD_PARAMETER, spread),
+            AudioMessage::Osc2Position(position) => self.set_parameter(OSC2_MODULE, wavetable::POSITION_PARAMETER, position),
+            AudioMessage::Osc2ThroughZero(through_zero) => if let Some(osc2) = self.modules.get_mut::<PolyWavetable>(OSC2_MODULE) {
+                osc2.set_through_zero(through_zero);
+            },
+            AudioMessage::Osc2Interpolation(interpolation) => if let Some(osc2) = self.modules.get_mut::<PolyWavetable>(OSC2_MODULE) {
+                osc2.set_interpolation(interpolation);
+            },
+
+            // Lfo1
+            AudioMessage::Lfo1Freq(freq) => self.set_parameter(LFO1_MODULE, lfo::FREQUENCY_PARAMETER, freq),
+            AudioMessage::Lfo1Shape(shape) => if let Some(lfo1) = self.modules.get_mut::<PolyLfo>(LFO1_MODULE) {
+                lfo1.set_shape(shape);
+            },
+            // Lfo2
+            AudioMessage::Lfo2Freq(freq) => self.set_parameter(LFO2_MODULE, lfo::FREQUENCY_PARAMETER, freq),
+            AudioMessage::Lfo2Shape(shape) => if let Some(lfo2) = self.modules.get_mut::<PolyLfo>(LFO2_MODULE) {
+                lfo2.set_shape(shape);
+            },
+
+            // Noise
+            AudioMessage::NoiseLevel(level) => self.set_parameter(NOISE_MODULE, noise::LEVEL_PARAMETER, level),
+            AudioMessage::NoiseShared(shared) => if let Some(noise) = self.modules.get_mut::<PolyNoise>(NOISE_MODULE) {
+                noise.set_shared(shared);
+            },
+
+            // Filter1
+            AudioMessage::Filter1Freq(freq) => self.set_parameter(FILTER1_MODULE, filter::FREQUENCY_PARAMETER, freq),
+            AudioMessage::Filter1Resonance(resonance) => self.set_parameter(FILTER1_MODULE, filter::RESONANCE_PARAMETER, resonance),
+            AudioMessage::Filter1Keytrack(keytrack) => self.set_parameter(FILTER1_MODULE, filter::KEYTRACK_PARAMETER, keytrack),
+            AudioMessage::Filter1CutoffAmount(amount) => self.set_parameter(FILTER1_MODULE, filter::CUTOFF_AMOUNT_PARAMETER, amount),
+            AudioMessage::Filter1Mode(mode) => if let Some(filter1) = self.modules.get_mut::<PolyFilter>(FILTER1_MODULE) {
+                filter1.set_mode(mode);
+            },
+            // Filter2
+            AudioMessage::Filter2Freq(freq) => self.set_parameter(FILTER2_MODULE, filter::FREQUENCY_PARAMETER, freq),
+            AudioMessage::Filter2Resonance(resonance) => self.set_parameter(FILTER2_MODULE, filter::RESONANCE_PARAMETER, resonance),
+            AudioMessage::Filter2Keytrack(keytrack) => self.set_parameter(FILTER2_MODULE, filter::KEYTRACK_PARAMETER, keytrack),
+            AudioMessage::Filter2CutoffAmount(amount) => self.set_parameter(FILTER2_MODULE, filter::CUTOFF_AMOUNT_PARAMETER, amount),
+            AudioMessage::Filter2Mode(mode) => if let Some(filter2) = self.modules.get_mut::<PolyFilter>(FILTER2_MODULE) {
+                filter2.set_mode(mode);
+            },
+            // Ladder
+            AudioMessage::LadderFreq(freq) => self.set_parameter(LADDER_MODULE, ladder::FREQUENCY_PARAMETER, freq),
+            AudioMessage::LadderResonance(resonance) => self.set_parameter(LADDER_MODULE, ladder::RESONANCE_PARAMETER, resonance),
+            AudioMessage::LadderDrive(drive) => self.set_parameter(LADDER_MODULE, ladder::DRIVE_PARAMETER, drive),
+
+            // Env1
+            AudioMessage::Env1Attack(attack) => self.set_parameter(ENV1_MODULE, envelope::ATTACK_PARAMETER, attack),
+            AudioMessage::Env1Decay(decay) => self.set_parameter(ENV1_MODULE, envelope::DECAY_PARAMETER, decay),
+            AudioMessage::Env1Sustain(sustain) => self.set_parameter(ENV1_MODULE, envelope::SUSTAIN_PARAMETER, sustain),
+            AudioMessage::Env1Release(release) => self.set_parameter(ENV1_MODULE, envelope::RELEASE_PARAMETER, release),
+            // Env2
+            AudioMessage::Env2Attack(attack) => self.set_parameter(ENV2_MODULE, envelope::ATTACK_PARAMETER, attack),
+            AudioMessage::Env2Decay(decay) => self.set_parameter(ENV2_MODULE, envelope::DECAY_PARAMETER, decay),
+            AudioMessage::Env2Sustain(sustain) => self.set_parameter(ENV2_MODULE, envelope::SUSTAIN_PARAMETER, sustain),
+            AudioMessage::Env2Release(release) => self.set_parameter(ENV2_MODULE, envelope::RELEASE_PARAMETER, release),
+            // Env3
+            AudioMessage::Env3Attack(attack) => self.set_parameter(ENV3_MODULE, envelope::ATTACK_PARAMETER, attack),
+            AudioMessage::Env3Decay(decay) => self.set_parameter(ENV3_MODULE, envelope::DECAY_PARAMETER, decay),
+            AudioMessage::Env3Sustain(sustain) => self.set_parameter(ENV3_MODULE, envelope::SUSTAIN_PARAMETER, sustain),
+            AudioMessage::Env3Release(release) => self.set_parameter(ENV3_MODULE, envelope::RELEASE_PARAMETER, release),
+
+            // Effects
+            // Distortion
+            AudioMessage::DistDrive(drive) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::DIST_DRIVE_PARAMETER, drive),
+            AudioMessage::DistWet(wet) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::DIST_WET_PARAMETER, wet),
+            // Delay
+            AudioMessage::DelayWet(wet) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::DELAY_WET_PARAMETER, wet),
+            AudioMessage::DelayFeedback(feedback) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::DELAY_FEEDBACK_PARAMETER, feedback),
+            AudioMessage::DelayTime(time) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::DELAY_TIME_PARAMETER, time),
+            // Reverb
+            AudioMessage::ReverbDamp(damp) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::REVERB_DAMP_PARAMETER, damp),
+            // Spread is a whole number of samples and jumps straight to the new value
+            AudioMessage::ReverbSpread(spread) => self.effects_chain.set_reverb_spread(spread),
+            AudioMessage::ReverbWet(wet) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::REVERB_WET_PARAMETER, wet),
+            AudioMessage::ReverbSpace(space) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::REVERB_SPACE_PARAMETER, space),
+
+            // Master
+            AudioMessage::MasterGain(gain) => self.set_parameter(EFFECTS_CHAIN_MODULE, effects::MASTER_GAIN_PARAMETER, gain),
+
+            // Midi
+            AudioMessage::KeyPress(velocity, note) => if let Some(midi) = self.modules.get_mut::<Midi>(MIDI_MODULE) {
+                midi.key_press(note, velocity);
+            },
+            AudioMessage::KeyRelease(note) => if let Some(midi) = self.modules.get_mut::<Midi>(MIDI_MODULE) {
+                midi.key_release(note);
+            },
+            AudioMessage::PedalPress => if let Some(midi) = self.modules.get_mut::<Midi>(MIDI_MODULE) {
+                midi.pedal_press();
+            },
+            AudioMessage::PedalRelease => if let Some(midi) = self.modules.get_mut::<Midi>(MIDI_MODULE) {
+                midi.pedal_release();
+            },
+
+            // Modules
+            AudioMessage::AddModule(id, slot) => {
+                // The effects chain id only routes cables, a module there would never be heard
+                let garbage = if id == EFFECTS_CHAIN_MODULE {
+                    Some(slot)
+                } else {
+                    self.modules.insert(id, slot)
+                };
+                if let Some(garbage) = garbage {
+                    self.defer_drop(SynthMessage::DropModule(garbage));
+                }
+                self.smoother.forget(id);
+                self.update_routes();
+            },
+            AudioMessage::RemoveModule(id) => {
+                // Cables to the module stay so cable indices keep matching the gui, they carry no signal
+                if let Some(garbage) = self.modules.remove(id) {
+                    self.defer_drop(SynthMessage::DropModule(garbage));
+                }
+                self.smoother.forget(id);
+                self.update_routes();
+            },
+            AudioMessage::ModuleParameter(id, parameter, value) => self.set_parameter(id, parameter, value),
+
+            // Cables
+            AudioMessage::CableConnection(target, source) => self.connect(source.port(), target.port()),
+            AudioMessage::PortConnection(target, source) => self.connect(source, target),
+            AudioMessage::CableAttenuation(cable_index, new_value) => self.cables.attenaute(cable_index, new_value, self.smoother.ramp_samples()),
+            AudioMessage::CableRemove(cable_index) => self.cables.remove_cable(cable_index),
+
+            // Processing
+            AudioMessage::SampleAccurateFeedback(enabled) => self.sample_accurate_feedback = enabled,
+            AudioMessage::ParameterSmoothing(time) => self.smoother.set_ramp_time(time, self.sample_rate),
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::ring_buffer;
+
+    fn garbage() -> SynthMessage {
+        SynthMessage::DropWavetable(Box::new(Wavetable::new(1)))
+    }
+
+    #[test]
+    fn deferred_drops_wait_for_the_queue() {
+        let (sender, mut receiver) = ring_buffer(2);
+        let mut audio_state = AudioState::new(Vec::new(), sender, 48000.0);
+        for _ in 0..5 {
+            audio_state.defer_drop(garbage());
+        }
+        assert_eq!(audio_state.pending_drops.len(), 3);
+
+        let mut received = 0;
+        while received < 5 {
+            while receiver.pop().is_some() {
+                received += 1;
+            }
+            audio_state.update();
+        }
+        assert!(audio_state.pending_drops.is_empty());
+        assert_eq!(audio_state.leaked_drops, 0);
+    }
+
+    #[test]
+    fn full_pending_drops_leak_instead_of_freeing() {
+        let (sender, _receiver) = ring_buffer(1);
+        let mut audio_state = AudioState::new(Vec::new(), sender, 48000.0);
+        for _ in 0..MAX_PENDING_DROPS + 3 {
+            audio_state.defer_drop(garbage());
+        }
+        assert_eq!(audio_state.pending_drops.len(), MAX_PENDING_DROPS);
+        assert_eq!(audio_state.leaked_drops, 2);
+    }
+
+    #[test]
+    fn first_knob_turn_ramps_from_the_panel_default() {
+        let (sender, _receiver) = ring_buffer(1);
+        let mut audio_state = AudioState::new(Vec::new(), sender, 48000.0);
+        assert_eq!(audio_state.smoother.chunk_len(BLOCK_SIZE), BLOCK_SIZE);
+
+        audio_state.handle_message(AudioMessage::Filter1Freq(0.0));
+        assert!(audio_state.smoother.chunk_len(BLOCK_SIZE) < BLOCK_SIZE);
+    }
+
+    #[test]
+    fn unison_spread_stays_stereo_through_a_filter() {
+        let (sender, _receiver) = ring_buffer(1);
+        let mut audio_state = AudioState::new(Vec::new(), sender, 48000.0);
+        let messages = [
+            AudioMessage::Osc1Unison(1.0),
+            AudioMessage::Osc1Spread(1.0),
+            AudioMessage::CableConnection(InputJack::Osc1Amp, OutputJack::MidiGate),
+            AudioMessage::CableConnection(InputJack::Filter1Value, OutputJack::Osc1Value),
+            AudioMessage::CableConnection(InputJack::EffectsChain, OutputJack::Filter1Value),
+            AudioMessage::KeyPress(60, 100),
+        ];
+        for msg in messages {
+            audio_state.handle_message(msg);
+        }
+
+        let mut left = [0.0; 4 * BLOCK_SIZE];
+        let mut right = [0.0; 4 * BLOCK_SIZE];
+        audio_state.process_block(&mut left, &mut right);
+        assert!(left.iter().any(|&sample| sample != 0.0));
+        assert!(left.iter().zip(right.iter()).any(|(left, right)| (left - right).abs() > 1e-3));
+    }
+}
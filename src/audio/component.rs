@@ -3,9 +3,13 @@ pub mod analog;
 pub mod cable;
 pub mod effects;
 pub mod filter;
+pub mod ladder;
 pub mod lfo;
+pub mod noise;
 pub mod wavetable;
 
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
 #[derive(Clone, Copy, Debug)]
 pub enum WaveShape {
     Saw,
@@ -23,4 +27,193 @@ impl Default for WaveShape {
 #[inline(always)]
 pub fn calculate_freq(voltage: f32) -> f32 {
     2.0_f32.powf((128.0 * voltage - 69.0) / 12.0) * 440.0
-}
\ No newline at end of file
+}
+
+/// Deepest linear FM, the frequency swings by this many times itself at full modulator level
+pub const MAX_FM_INDEX: f32 = 8.0;
+
+/// Linear FM around `frequency`
+///
+/// The deviation scales with the carrier so a patch keeps its timbre and tuning across the keyboard.
+/// Through zero lets the frequency go negative and run the phase backwards, otherwise it stops at 0 Hz.
+#[inline(always)]
+pub fn linear_fm(frequency: f32, modulator: f32, index: f32, through_zero: bool) -> f32 {
+    let frequency = frequency * (1.0 + index * modulator);
+    if through_zero {
+        frequency
+    } else {
+        frequency.max(0.0)
+    }
+}
+
+/// Most detuned copies an oscillator stacks per voice
+pub const MAX_UNISON: usize = 8;
+/// Detune of the outermost copies at full detune, in semitones either side
+const MAX_DETUNE: f32 = 0.5;
+
+/// Unison settings shared by the oscillators
+///
+/// Copies are spread evenly between the lowest and the highest detune, and from left to right in the
+/// stereo field. The mix is scaled by one over the square root of the count so stacking keeps about
+/// the same loudness.
+pub struct Unison {
+    count: usize,
+    detune: f32,
+    spread: f32,
+    ratios: [f64; MAX_UNISON],
+    /// -1 is hard left, 1 hard right
+    pans: [f32; MAX_UNISON],
+    gain: f32,
+    rng: StdRng,
+}
+
+impl Unison {
+    pub fn new() -> Self {
+        Self {
+            count: 1,
+            detune: 0.0,
+            spread: 0.0,
+            ratios: [1.0; MAX_UNISON],
+            pans: [0.0; MAX_UNISON],
+            gain: 1.0,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the copies that were just switched on, they need fresh phases
+    pub fn set_count_value(&mut self, count: f32) -> std::ops::Range<usize> {
+        let old_count = self.count;
+        self.count = 1 + (count.clamp(0.0, 1.0) * (MAX_UNISON - 1) as f32).round() as usize;
+        self.update();
+        old_count.min(self.count)..self.count
+    }
+
+    pub fn set_detune_value(&mut self, detune: f32) {
+        self.detune = detune.clamp(0.0, 1.0);
+        self.update();
+    }
+
+    pub fn set_spread_value(&mut self, spread: f32) {
+        self.spread = spread.clamp(0.0, 1.0);
+        self.update();
+    }
+
+    /// Frequency multiplier of a copy
+    #[inline(always)]
+    pub fn ratio(&self, copy: usize) -> f64 {
+        self.ratios[copy]
+    }
+
+    #[inline(always)]
+    pub fn pan(&self, copy: usize) -> f32 {
+        self.pans[copy]
+    }
+
+    #[inline(always)]
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Start phase of a newly switched on copy, as a share of the cycle
+    pub fn random_phase(&mut self) -> f64 {
+        self.rng.gen_range(0.0..1.0)
+    }
+
+    fn update(&mut self) {
+        let middle = (self.count - 1) / 2;
+        for copy in 0..self.count {
+            // Copies fan out from the middle so the first one, which drives the sync output, stays closest to the played pitch
+            let offset = (copy + 1) / 2;
+            let slot = if copy % 2 == 1 { middle + offset } else { middle - offset };
+            let position = if self.count == 1 {
+                0.0
+            } else {
+                2.0 * slot as f32 / (self.count - 1) as f32 - 1.0
+            };
+            self.ratios[copy] = 2.0_f64.powf((position * self.detune * MAX_DETUNE / 12.0) as f64);
+            self.pans[copy] = position * self.spread;
+        }
+        self.gain = 1.0 / (self.count as f32).sqrt();
+    }
+}
+
+const CUTOFF_TABLE_SIZE: usize = 1024;
+/// Highest cutoff as a share of the sample rate, `tan` runs off to infinity at Nyquist
+const MAX_CUTOFF_RATIO: f32 = 0.49;
+
+/// Prewarped filter cutoff `tan(pi * frequency / sample_rate)` sampled over the voltage range
+///
+/// Steps are an eighth of a semitone, close enough to `calculate_freq` plus `tan` that audio rate
+/// cutoff modulation sounds the same for a fraction of the cost.
+pub struct CutoffTable {
+    sample_rate: f32,
+    table: [f32; CUTOFF_TABLE_SIZE + 1],
+}
+
+impl CutoffTable {
+    pub fn new() -> Self {
+        Self {
+            sample_rate: 0.0,
+            table: [0.0; CUTOFF_TABLE_SIZE + 1],
+        }
+    }
+
+    /// Refills the table if the sample rate changed since the last call
+    pub fn prepare(&mut self, sample_rate: f32) {
+        if self.sample_rate == sample_rate {
+            return;
+        }
+        self.sample_rate = sample_rate;
+        for (i, value) in self.table.iter_mut().enumerate() {
+            let frequency = calculate_freq(i as f32 / CUTOFF_TABLE_SIZE as f32).min(MAX_CUTOFF_RATIO * sample_rate);
+            *value = (std::f32::consts::PI * frequency / sample_rate).tan();
+        }
+    }
+
+    #[inline(always)]
+    pub fn lookup(&self, voltage: f32) -> f32 {
+        let position = voltage.min(1.0).max(0.0) * CUTOFF_TABLE_SIZE as f32;
+        let index = (position as usize).min(CUTOFF_TABLE_SIZE - 1);
+        let fraction = position - index as f32;
+        self.table[index] + (self.table[index + 1] - self.table[index]) * fraction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact_cutoff(voltage: f32, sample_rate: f32) -> f32 {
+        let frequency = calculate_freq(voltage).min(MAX_CUTOFF_RATIO * sample_rate);
+        (std::f32::consts::PI * frequency / sample_rate).tan()
+    }
+
+    #[test]
+    fn cutoff_table_matches_tan_within_a_hundredth_of_a_percent() {
+        for sample_rate in [44100.0, 96000.0] {
+            let mut table = CutoffTable::new();
+            table.prepare(sample_rate);
+            for step in 0..=10000 {
+                let voltage = step as f32 / 10000.0;
+                let error = (table.lookup(voltage) / exact_cutoff(voltage, sample_rate) - 1.0).abs();
+                assert!(error < 1e-4, "{error} off at {voltage} and {sample_rate} Hz");
+            }
+        }
+    }
+
+    #[test]
+    fn cutoff_table_refills_when_the_rate_changes() {
+        let mut table = CutoffTable::new();
+        table.prepare(44100.0);
+        let at_44100 = table.lookup(0.9);
+        table.prepare(96000.0);
+        assert!((table.lookup(0.9) / exact_cutoff(0.9, 96000.0) - 1.0).abs() < 1e-4);
+        assert!(table.lookup(0.9) < at_44100);
+        table.prepare(44100.0);
+        assert_eq!(table.lookup(0.9), at_44100);
+    }
+}
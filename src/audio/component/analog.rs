@@ -1,31 +1,64 @@
-use crate::audio::MAX_POLY_COUNT;
-use super::WaveShape;
+use crate::audio::{module::Module, Block, MAX_POLY_COUNT};
+use super::{Unison, WaveShape, MAX_UNISON};
 
 pub const LEVEL_INPUT: usize = 0 * MAX_POLY_COUNT;
 pub const FREQUENCY_INPUT: usize = 1 * MAX_POLY_COUNT;
 pub const PHASE_INPUT: usize = 2 * MAX_POLY_COUNT;
 pub const AMP_INPUT: usize = 3 * MAX_POLY_COUNT;
-pub const TOTAL_INPUT_COUNT: usize = 4 * MAX_POLY_COUNT;
+pub const PULSE_WIDTH_INPUT: usize = 4 * MAX_POLY_COUNT;
+/// Hard sync, takes another oscillator's `SYNC_OUTPUT`
+pub const SYNC_INPUT: usize = 5 * MAX_POLY_COUNT;
+/// Linear FM, scaled by the FM index
+pub const FM_INPUT: usize = 6 * MAX_POLY_COUNT;
+pub const TOTAL_INPUT_COUNT: usize = 7 * MAX_POLY_COUNT;
 
 pub const OUT_VALUE: usize = 0 * MAX_POLY_COUNT;
-pub const TOTAL_OUTPUT_COUNT: usize = 1 * MAX_POLY_COUNT;
+/// Samples until the phase wraps when that happens before the next sample, otherwise 0
+pub const SYNC_OUTPUT: usize = 1 * MAX_POLY_COUNT;
+/// Stereo side signal of the unison spread, half of left minus right, cables from `OUT_VALUE` carry it along
+pub const SIDE_OUTPUT: usize = 2 * MAX_POLY_COUNT;
+pub const TOTAL_OUTPUT_COUNT: usize = 3 * MAX_POLY_COUNT;
 
-pub struct PolyAnalog<const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> {
+pub const LEVEL_PARAMETER: usize = 0;
+pub const FREQUENCY_PARAMETER: usize = 1;
+pub const PHASE_PARAMETER: usize = 2;
+pub const PULSE_WIDTH_PARAMETER: usize = 3;
+pub const FM_INDEX_PARAMETER: usize = 4;
+pub const UNISON_PARAMETER: usize = 5;
+pub const DETUNE_PARAMETER: usize = 6;
+pub const SPREAD_PARAMETER: usize = 7;
+
+/// Narrowest pulse, both edges still need a few samples between them at high pitches
+const MIN_PULSE_WIDTH: f64 = 0.05;
+const MAX_PULSE_WIDTH: f64 = 0.95;
+
+pub struct PolyAnalog {
     shape: WaveShape,
     level: f32,
     phase: f32,
     frequency: f32,
-    current_phases: [f64; MAX_POLY_COUNT],
+    pulse_width: f32,
+    fm_index: f32,
+    through_zero: bool,
+    unison: Unison,
+    current_phases: [[f64; MAX_UNISON]; MAX_POLY_COUNT],
+    /// Second half of the band-limited step of a sync reset, added to the sample after it in place of the wrap's blep
+    sync_corrections: [[Option<f64>; MAX_UNISON]; MAX_POLY_COUNT],
 }
 
-impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyAnalog <INPUT_OFFSET, OUTPUT_OFFSET> {
+impl PolyAnalog {
     pub fn new() -> Self {
         Self {
             shape: WaveShape::default(),
             level: 0.5,
             phase: 0.0,
             frequency: 0.0,
-            current_phases: [0.0; MAX_POLY_COUNT],
+            pulse_width: 0.5,
+            fm_index: 0.0,
+            through_zero: false,
+            unison: Unison::new(),
+            current_phases: [[0.0; MAX_UNISON]; MAX_POLY_COUNT],
+            sync_corrections: [[None; MAX_UNISON]; MAX_POLY_COUNT],
         }
     }
 
@@ -45,39 +78,161 @@ impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyAnalog <INPUT_O
         self.level = level;
     }
 
-    #[inline(always)]
-    pub fn render(&mut self, inputs: &[f32], outputs: &mut [f32], sample_rate: f64) {
-        for (analog, current_phase) in self.current_phases.iter_mut().enumerate() {
-            let phase_input = inputs[INPUT_OFFSET + PHASE_INPUT + analog];
-            let frequency_input = inputs[INPUT_OFFSET + FREQUENCY_INPUT + analog];
-            let level_input = inputs[INPUT_OFFSET + LEVEL_INPUT + analog];
-            let amp_input = inputs[INPUT_OFFSET + AMP_INPUT + analog];
+    pub fn set_pulse_width_value(&mut self, pulse_width: f32) {
+        self.pulse_width = pulse_width;
+    }
+
+    pub fn set_fm_index_value(&mut self, fm_index: f32) {
+        self.fm_index = fm_index * super::MAX_FM_INDEX;
+    }
+
+    pub fn set_through_zero(&mut self, through_zero: bool) {
+        self.through_zero = through_zero;
+    }
+
+    pub fn set_unison_value(&mut self, unison: f32) {
+        for copy in self.unison.set_count_value(unison) {
+            for (current_phases, sync_corrections) in self.current_phases.iter_mut().zip(self.sync_corrections.iter_mut()) {
+                current_phases[copy] = self.unison.random_phase();
+                sync_corrections[copy] = None;
+            }
+        }
+    }
+
+    pub fn set_detune_value(&mut self, detune: f32) {
+        self.unison.set_detune_value(detune);
+    }
+
+    pub fn set_spread_value(&mut self, spread: f32) {
+        self.unison.set_spread_value(spread);
+    }
+}
+
+impl Module for PolyAnalog {
+    fn input_count(&self) -> usize {
+        TOTAL_INPUT_COUNT
+    }
+
+    fn output_count(&self) -> usize {
+        TOTAL_OUTPUT_COUNT
+    }
+
+    fn side_output(&self, jack: usize) -> Option<usize> {
+        (jack == OUT_VALUE).then_some(SIDE_OUTPUT)
+    }
+
+    fn set_parameter(&mut self, parameter: usize, value: f32) {
+        match parameter {
+            LEVEL_PARAMETER => self.set_level_value(value),
+            FREQUENCY_PARAMETER => self.set_freq_value(value),
+            PHASE_PARAMETER => self.set_phase_value(value),
+            PULSE_WIDTH_PARAMETER => self.set_pulse_width_value(value),
+            FM_INDEX_PARAMETER => self.set_fm_index_value(value),
+            UNISON_PARAMETER => self.set_unison_value(value),
+            DETUNE_PARAMETER => self.set_detune_value(value),
+            SPREAD_PARAMETER => self.set_spread_value(value),
+            _ => {},
+        }
+    }
+
+    fn render(&mut self, inputs: &[Block], outputs: &mut [Block], len: usize, sample_rate: f64) {
+        let voices = self.current_phases.iter_mut().zip(self.sync_corrections.iter_mut());
+        for (analog, (current_phases, sync_corrections)) in voices.enumerate() {
+            let phase_inputs = &inputs[PHASE_INPUT + analog];
+            let frequency_inputs = &inputs[FREQUENCY_INPUT + analog];
+            let level_inputs = &inputs[LEVEL_INPUT + analog];
+            let amp_inputs = &inputs[AMP_INPUT + analog];
+            let pulse_width_inputs = &inputs[PULSE_WIDTH_INPUT + analog];
+            let sync_inputs = &inputs[SYNC_INPUT + analog];
+            let fm_inputs = &inputs[FM_INPUT + analog];
+            let [output, sync_output, side_output] = outputs
+                .get_disjoint_mut([OUT_VALUE + analog, SYNC_OUTPUT + analog, SIDE_OUTPUT + analog])
+                .unwrap();
+
+            for i in 0..len {
+                let level = self.level + level_inputs[i];
+                let voltage = self.frequency + frequency_inputs[i];
+                let frequency =  super::calculate_freq(voltage);// C-1 (midi note 0)
+                let frequency = super::linear_fm(frequency, fm_inputs[i], self.fm_index, self.through_zero);
+                let phase_offset = phase_inputs[i] as f64;
+                let width = ((self.pulse_width + pulse_width_inputs[i]) as f64).clamp(MIN_PULSE_WIDTH, MAX_PULSE_WIDTH);
+                let sync = sync_inputs[i] as f64;
+
+                let mut mid = 0.0;
+                let mut side = 0.0;
+                for copy in 0..self.unison.count() {
+                    let current_phase = &mut current_phases[copy];
+                    let sync_correction = &mut sync_corrections[copy];
+                    let phase = (*current_phase + phase_offset) % 1.0;
 
-            let level = self.level + level_input;
-            let voltage = self.frequency + frequency_input;
-            let frequency =  super::calculate_freq(voltage);// C-1 (midi note 0)
-            let phase = (*current_phase + phase_input as f64) % 1.0;
+                    // Negative under through zero FM, the blep only cares how far the phase moves
+                    let phase_increment = frequency as f64 * self.unison.ratio(copy) / sample_rate;
+                    let blep_width = phase_increment.abs();
 
-            let phase_increment = frequency as f64 / sample_rate;
+                    // The sample after a reset already carries its step, and a reset before the wrap means there is no wrap
+                    let reset_correction = sync_correction.take();
+                    let wrap_blep = if reset_correction.is_some() || (sync > 0.0 && phase + sync * phase_increment < 1.0) {
+                        0.0
+                    } else {
+                        poly_blep(phase, blep_width)
+                    };
+                    let mut raw = match self.shape {
+                        WaveShape::Saw => naive_wave(self.shape, phase, width) - wrap_blep,
+                        WaveShape::Sine | WaveShape::Triangle => naive_wave(self.shape, phase, width),
+                        WaveShape::Square => {
+                            let raw = naive_wave(self.shape, phase, width);
+                            raw + wrap_blep - poly_blep((phase + 1.0 - width) % 1.0, blep_width)
+                        },
+                    };
+                    raw += reset_correction.unwrap_or(0.0);
 
-            let raw = match self.shape {
-                WaveShape::Saw => 2.0 * phase - 1.0 - poly_blep(phase, phase_increment),
-                WaveShape::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
-                WaveShape::Square => {
-                    let raw = if phase < 0.5 {1.0} else {-1.0};
-                    raw + poly_blep(phase, phase_increment) - poly_blep((phase + 0.5) % 1.0, phase_increment)
-                },
-                WaveShape::Triangle => 1.0 - 4.0 * (phase - (phase + 0.5).floor()).abs(),
-            };
+                    let next_phase = *current_phase + phase_increment;
+                    if copy == 0 {
+                        sync_output[i] = if next_phase >= 1.0 {
+                            ((1.0 - *current_phase) / phase_increment) as f32
+                        } else if next_phase < 0.0 {
+                            (*current_phase / -phase_increment) as f32
+                        } else {
+                            0.0
+                        };
+                    }
 
-            *current_phase = (*current_phase + phase_increment) % 1.0;
+                    if sync > 0.0 {
+                        // The reset lands `sync` samples from now, spread the step over this sample and the next one
+                        let before = naive_wave(self.shape, (phase + sync * phase_increment).rem_euclid(1.0), width);
+                        let after = naive_wave(self.shape, phase_offset.rem_euclid(1.0), width);
+                        let half_step = (after - before) / 2.0;
+                        raw += half_step * (1.0 - sync) * (1.0 - sync);
+                        *sync_correction = Some(-half_step * sync * sync);
+                        *current_phase = ((1.0 - sync) * phase_increment).rem_euclid(1.0);
+                    } else {
+                        *current_phase = next_phase.rem_euclid(1.0);
+                    }
 
-            let scaled_raw = raw as f32 * level * amp_input;
-            outputs[OUTPUT_OFFSET + OUT_VALUE + analog] = scaled_raw;
+                    let raw = raw as f32;
+                    mid += raw;
+                    side -= raw * self.unison.pan(copy);
+                }
+
+                let gain = self.unison.gain() * level * amp_inputs[i];
+                output[i] = mid * gain;
+                side_output[i] = side * gain;
+            }
         }
     }
 }
 
+/// Waveform without any band-limiting
+#[inline(always)]
+fn naive_wave(shape: WaveShape, phase: f64, width: f64) -> f64 {
+    match shape {
+        WaveShape::Saw => 2.0 * phase - 1.0,
+        WaveShape::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
+        WaveShape::Square => if phase < width {1.0} else {-1.0},
+        WaveShape::Triangle => 1.0 - 4.0 * (phase - (phase + 0.5).floor()).abs(),
+    }
+}
+
 fn poly_blep(phase: f64, phase_increment: f64) -> f64 {
     if phase < phase_increment {
         let t = phase / phase_increment;
@@ -88,4 +243,71 @@ fn poly_blep(phase: f64, phase_increment: f64) -> f64 {
     } else {
         0.0
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::BLOCK_SIZE;
+
+    #[test]
+    fn synced_saw_stays_within_the_blep_residual() {
+        const SAMPLE_RATE: f64 = 48000.0;
+        const BLOCKS: usize = 64;
+        let voltage = 0.75;
+        let phase_increment = crate::audio::component::calculate_freq(voltage) as f64 / SAMPLE_RATE;
+        let master_increment = 0.0137;
+
+        let mut analog = PolyAnalog::new();
+        analog.set_shape(WaveShape::Saw);
+        analog.set_level_value(1.0);
+        let mut inputs = vec![[0.0; BLOCK_SIZE]; TOTAL_INPUT_COUNT];
+        let mut outputs = vec![[0.0; BLOCK_SIZE]; TOTAL_OUTPUT_COUNT];
+        inputs[FREQUENCY_INPUT] = [voltage; BLOCK_SIZE];
+        inputs[AMP_INPUT] = [1.0; BLOCK_SIZE];
+
+        // Naive saw with the resets at the same fractional times, and how far the blep may move each sample from it
+        let mut master_phase = 0.0;
+        let mut slave_phase = 0.0;
+        let mut rendered = Vec::new();
+        let mut naive = Vec::new();
+        let mut allowed = vec![0.0; BLOCK_SIZE * BLOCKS + 1];
+        // Starting at phase 0 looks like a wrap just before the first sample
+        allowed[0] = 1.0;
+        for block in 0..BLOCKS {
+            for (i, sync_input) in inputs[SYNC_INPUT].iter_mut().enumerate() {
+                let n = block * BLOCK_SIZE + i;
+                let sync = if master_phase + master_increment >= 1.0 {
+                    ((1.0 - master_phase) / master_increment) as f32
+                } else {
+                    0.0
+                };
+                master_phase = (master_phase + master_increment) % 1.0;
+                *sync_input = sync;
+
+                naive.push(naive_wave(WaveShape::Saw, slave_phase, 0.5));
+                let sync = sync as f64;
+                let (step, next_phase) = if sync > 0.0 && slave_phase + sync * phase_increment < 1.0 {
+                    (-2.0 * (slave_phase + sync * phase_increment), (1.0 - sync) * phase_increment)
+                } else if sync > 0.0 {
+                    (2.0, (1.0 - sync) * phase_increment)
+                } else if slave_phase + phase_increment >= 1.0 {
+                    (2.0, slave_phase + phase_increment - 1.0)
+                } else {
+                    (0.0, slave_phase + phase_increment)
+                };
+                let half_step: f64 = step.abs() / 2.0;
+                allowed[n] = half_step.max(allowed[n]);
+                allowed[n + 1] = half_step;
+                slave_phase = next_phase;
+            }
+            analog.render(&inputs, &mut outputs, BLOCK_SIZE, SAMPLE_RATE);
+            rendered.extend_from_slice(&outputs[OUT_VALUE]);
+        }
+
+        for (n, (&rendered, &naive)) in rendered.iter().zip(naive.iter()).enumerate() {
+            let error = (rendered as f64 - naive).abs();
+            assert!(error <= allowed[n] + 1e-5, "sample {n} is {error} away from the naive saw, allowed {}", allowed[n]);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{audio::{InputJack, OutputJack, MAX_POLY_COUNT}, common::ComponentVec};
+use crate::{audio::{module::{ModuleId, PortId}, smoothing::Ramp, Block, MAX_POLY_COUNT}, common::ComponentVec};
 
 pub struct Cables <const MAX_CABLES: usize> (ComponentVec <Cable, MAX_CABLES>);
 
@@ -7,42 +7,106 @@ impl <const MAX_CABLES: usize> Cables <MAX_CABLES> {
         Self (ComponentVec::new())
     }
 
-    pub fn add_cable(&mut self, source: OutputJack, target: InputJack) -> Result<(), ()> {
-        self.0.push(Cable::new(source, target))
+    pub fn add_cable(&mut self, source: PortId, target: PortId, side: Option<(PortId, PortId)>, feedback: bool) -> Result<(), ()> {
+        self.0.push(Cable::new(source, target, side, feedback))
     }
 
     pub fn remove_cable(&mut self, cable_index: usize) {
-        self.0.remove(cable_index);
+        if cable_index < self.0.len() {
+            self.0.remove(cable_index);
+        }
+    }
+
+    pub fn has_feedback(&self) -> bool {
+        self.0.iter().any(|cable| cable.feedback)
+    }
+
+    /// Reclassifies every cable after the render order changed
+    pub fn update_feedback(&mut self, is_feedback: impl Fn(PortId, PortId) -> bool) {
+        for cable in self.0.iter_mut() {
+            cable.feedback = is_feedback(cable.source, cable.target);
+        }
     }
 
+    /// Looks the side ports up again after modules at either end were replaced
+    pub fn update_sides(&mut self, side_route: impl Fn(PortId, PortId) -> Option<(PortId, PortId)>) {
+        for cable in self.0.iter_mut() {
+            cable.side = side_route(cable.source, cable.target);
+        }
+    }
+
+    /// Sums every cable ending in the `target` module into its input blocks
+    ///
+    /// Cables to ports a module does not have, or to modules that were removed, carry no signal.
     #[inline(always)]
-    pub fn run_cables(&self, inputs: &mut [f32], outputs: &[f32]) {
-        inputs.fill(0.0);
-        for cable in self.0.iter() {
-            for i in 0..MAX_POLY_COUNT {
-                inputs[cable.target as usize + i] += outputs[cable.source as usize + i] * cable.gain;
+    pub fn run_cables(&self, inputs: &mut [Block], outputs: &[Vec<Block>], target: ModuleId, len: usize) {
+        for input in inputs.iter_mut() {
+            input[..len].fill(0.0);
+        }
+        self.sum_cables(inputs, outputs, target, len);
+    }
+
+    /// Like `run_cables` but adds onto whatever the inputs already hold
+    #[inline(always)]
+    pub fn sum_cables(&self, inputs: &mut [Block], outputs: &[Vec<Block>], target: ModuleId, len: usize) {
+        for cable in self.0.iter().filter(|cable| cable.target.module == target) {
+            cable.sum_port(inputs, outputs, cable.source, cable.target, len);
+            if let Some((source, target)) = cable.side {
+                cable.sum_port(inputs, outputs, source, target, len);
             }
         }
     }
 
-    pub fn attenaute(&mut self, cable_index: usize, new_value: f32) {
-        self.0[cable_index].gain = new_value;
+    /// Ramps the cable's gain to `new_value` over `ramp_samples`
+    pub fn attenaute(&mut self, cable_index: usize, new_value: f32, ramp_samples: usize) {
+        if let Some(cable) = self.0.get_mut(cable_index) {
+            cable.gain.set_target(new_value, ramp_samples);
+        }
+    }
+
+    /// Moves gain ramps on once every module has read the rendered chunk
+    pub fn advance(&mut self, len: usize) {
+        for cable in self.0.iter_mut() {
+            cable.gain.advance(len);
+        }
     }
 }
 
 #[derive(Debug)]
 struct Cable {
-    source: OutputJack,
-    target: InputJack,
-    gain: f32,
+    source: PortId,
+    target: PortId,
+    /// Stereo side ports carried along with the value, when both ends have one
+    side: Option<(PortId, PortId)>,
+    gain: Ramp,
+    feedback: bool,
 }
 
 impl Cable {
-    pub fn new(source: OutputJack, target: InputJack) -> Self {
+    pub fn new(source: PortId, target: PortId, side: Option<(PortId, PortId)>, feedback: bool) -> Self {
         Self {
             source,
             target,
-            gain: 1.0,
+            side,
+            gain: Ramp::new(1.0),
+            feedback,
         }
     }
-}
\ No newline at end of file
+
+    #[inline(always)]
+    fn sum_port(&self, inputs: &mut [Block], outputs: &[Vec<Block>], source: PortId, target: PortId, len: usize) {
+        let source = outputs.get(source.module)
+            .and_then(|outputs| outputs.get(source.jack..source.jack + MAX_POLY_COUNT));
+        let Some(source) = source else {
+            return;
+        };
+        let Some(target) = inputs.get_mut(target.jack..target.jack + MAX_POLY_COUNT) else {
+            return;
+        };
+        for (input, output) in target.iter_mut().zip(source) {
+            for (i, (input, output)) in input[..len].iter_mut().zip(&output[..len]).enumerate() {
+                *input += output * self.gain.value_at(i);
+            }
+        }
+    }
+}
@@ -1,11 +1,28 @@
 use core::f64;
-use std::collections::VecDeque;
 
-use crate::audio::MAX_POLY_COUNT;
+use crate::audio::{Block, MAX_POLY_COUNT};
+
+pub const VALUE_INPUT: usize = 0 * MAX_POLY_COUNT;
+/// Stereo side signal, half of left minus right, added to the left channel and taken from the right
+///
+/// Cables into `VALUE_INPUT` bring their source's side here, a cable patched to this jack adds onto that.
+pub const SIDE_INPUT: usize = 1 * MAX_POLY_COUNT;
+pub const TOTAL_INPUT_COUNT: usize = 2 * MAX_POLY_COUNT;
+
+pub const DIST_DRIVE_PARAMETER: usize = 0;
+pub const DIST_WET_PARAMETER: usize = 1;
+pub const DELAY_FEEDBACK_PARAMETER: usize = 2;
+pub const DELAY_WET_PARAMETER: usize = 3;
+pub const DELAY_TIME_PARAMETER: usize = 4;
+pub const REVERB_DAMP_PARAMETER: usize = 5;
+pub const REVERB_WET_PARAMETER: usize = 6;
+pub const REVERB_SPACE_PARAMETER: usize = 7;
+pub const MASTER_GAIN_PARAMETER: usize = 8;
 
 pub struct EffectsChain {
     distortion: Distortion,
-    delay: Delay,
+    /// Left and right
+    delays: [Delay; 2],
     reverb: Reverb,
     master_gain: f32,
 }
@@ -14,12 +31,28 @@ impl EffectsChain {
     pub fn new(sample_rate: f64) -> Self {
         Self {
             distortion: Distortion::new(),
-            delay: Delay::new(),
+            delays: [Delay::new(sample_rate), Delay::new(sample_rate)],
             reverb: Reverb::new(sample_rate),
             master_gain: 0.7,
         }
     }
 
+    /// Continuous knobs, indexed by the `*_PARAMETER` constants so they can be smoothed like module parameters
+    pub fn set_parameter(&mut self, parameter: usize, value: f32) {
+        match parameter {
+            DIST_DRIVE_PARAMETER => self.set_dist_drive(value),
+            DIST_WET_PARAMETER => self.set_dist_wet(value),
+            DELAY_FEEDBACK_PARAMETER => self.set_delay_feedback(value),
+            DELAY_WET_PARAMETER => self.set_delay_wet(value),
+            DELAY_TIME_PARAMETER => self.set_delay_time(value),
+            REVERB_DAMP_PARAMETER => self.set_reverb_damp(value),
+            REVERB_WET_PARAMETER => self.set_reverb_wet(value),
+            REVERB_SPACE_PARAMETER => self.set_reverb_space(value),
+            MASTER_GAIN_PARAMETER => self.set_master_gain(value),
+            _ => {},
+        }
+    }
+
     pub fn set_dist_drive(&mut self, drive: f32) {
         self.distortion.drive = 1.0 + drive * 5.0;
     }
@@ -28,18 +61,22 @@ impl EffectsChain {
         self.distortion.wet = wet;
     }
 
-    pub fn set_delay_time(&mut self, value: f32, sample_rate: f32) {
-        let delay_index = (value * sample_rate) as usize;
-        self.delay.delay_index = delay_index;
-        self.delay.buffer.shrink_to(delay_index);
+    pub fn set_delay_time(&mut self, value: f32) {
+        for delay in self.delays.iter_mut() {
+            delay.set_time(value * MAX_DELAY_TIME);
+        }
     }
 
     pub fn set_delay_feedback(&mut self, feedback: f32) {
-        self.delay.feedback = feedback;
+        for delay in self.delays.iter_mut() {
+            delay.feedback = feedback;
+        }
     }
 
     pub fn set_delay_wet(&mut self, wet: f32) {
-        self.delay.wet = wet;
+        for delay in self.delays.iter_mut() {
+            delay.wet = wet;
+        }
     }
 
     pub fn set_reverb_damp(&mut self, damp: f32) {
@@ -47,7 +84,7 @@ impl EffectsChain {
     }
 
     pub fn set_reverb_spread(&mut self, spread: f32) {
-        self.reverb.stereo_spread = (100 as f32 * spread) as usize;
+        self.reverb.set_spread(spread);
         self.set_reverb_wet(1.0 - self.reverb.dry);
     }
 
@@ -65,12 +102,18 @@ impl EffectsChain {
         self.master_gain = gain;
     }
 
+    /// Mixes every voice block down to left and right and runs them through the chain
     #[inline(always)]
-    pub fn render(&mut self, inputs: &[f32; MAX_POLY_COUNT]) -> (f32, f32) {
-        let input = inputs.iter().sum();
-        let distorted = self.distortion.render(input);
-        let delayed = self.delay.render(distorted);
-        self.reverb.render(delayed * self.master_gain)
+    pub fn render(&mut self, inputs: &[Block], left: &mut [f32], right: &mut [f32]) {
+        let (values, sides) = inputs.split_at(SIDE_INPUT);
+        for (i, (left, right)) in left.iter_mut().zip(right.iter_mut()).enumerate() {
+            let value: f32 = values.iter().map(|voice| voice[i]).sum();
+            let side: f32 = sides.iter().map(|voice| voice[i]).sum();
+            let [delay_left, delay_right] = &mut self.delays;
+            let delayed_left = delay_left.render(self.distortion.render(value + side));
+            let delayed_right = delay_right.render(self.distortion.render(value - side));
+            (*left, *right) = self.reverb.render(delayed_left * self.master_gain, delayed_right * self.master_gain);
+        }
     }
 }
 
@@ -96,30 +139,94 @@ impl Distortion {
     }
 }
 
+const MAX_DELAY_TIME: f32 = 1.0;
+const INIT_DELAY_TIME: f32 = 0.5;
+/// How long the read head takes to mostly catch up with a new delay time, gives the tape-style pitch bend
+const DELAY_GLIDE_TIME: f32 = 0.1;
+
+/// Fixed size ring buffer, allocated once so lines never grow or shrink while rendering
+struct DelayLine {
+    buffer: Box<[f32]>,
+    write_index: usize,
+}
+
+impl DelayLine {
+    /// Line that can be read back up to `max_delay` samples
+    fn new(max_delay: usize) -> Self {
+        Self {
+            buffer: vec![0.0; max_delay.max(1)].into_boxed_slice(),
+            write_index: 0,
+        }
+    }
+
+    fn max_delay(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Sample written `delay` samples ago, `delay` has to be in `1..=max_delay`
+    #[inline(always)]
+    fn read(&self, delay: usize) -> f32 {
+        let len = self.buffer.len();
+        self.buffer[(self.write_index + len - delay) % len]
+    }
+
+    /// Linear interpolation between whole sample reads, `delay` has to be in `1.0..max_delay`
+    #[inline(always)]
+    fn read_interpolated(&self, delay: f32) -> f32 {
+        let whole = delay as usize;
+        let newer = self.read(whole);
+        let older = self.read(whole + 1);
+        newer + (older - newer) * delay.fract()
+    }
+
+    #[inline(always)]
+    fn write(&mut self, value: f32) {
+        self.buffer[self.write_index] = value;
+        self.write_index = (self.write_index + 1) % self.buffer.len();
+    }
+}
+
 struct Delay {
     wet: f32,
     feedback: f32,
-    delay_index: usize,
+    sample_rate: f32,
+    /// Delay time in samples the read head glides towards
+    target_delay: f32,
+    current_delay: f32,
+    glide: f32,
     // State
-    buffer: VecDeque<f32>,
+    line: DelayLine,
 }
 
 impl Delay {
-    fn new() -> Self {
+    fn new(sample_rate: f64) -> Self {
+        let sample_rate = sample_rate as f32;
+        let init_delay = INIT_DELAY_TIME * sample_rate;
         Self {
             wet: 0.0,
             feedback: 0.7,
-            delay_index: 24000,
-            buffer: VecDeque::new(),
+            sample_rate,
+            target_delay: init_delay,
+            current_delay: init_delay,
+            glide: 1.0 - (-1.0 / (DELAY_GLIDE_TIME * sample_rate)).exp(),
+            // Room for the longest delay plus the sample after it for interpolation
+            line: DelayLine::new((MAX_DELAY_TIME * sample_rate) as usize + 2),
         }
     }
 
+    fn set_time(&mut self, seconds: f32) {
+        let max_delay = (self.line.max_delay() - 2) as f32;
+        self.target_delay = (seconds * self.sample_rate).clamp(1.0, max_delay);
+    }
+
     #[inline(always)]
     fn render(&mut self, input: f32) -> f32 {
-        let wet_value = self.buffer.remove(self.delay_index).unwrap_or_default();
+        self.current_delay += (self.target_delay - self.current_delay) * self.glide;
+
+        let wet_value = self.line.read_interpolated(self.current_delay);
         let out_value = input + (wet_value - input) * self.wet;
 
-        self.buffer.push_front((input + wet_value) * self.feedback);
+        self.line.write((input + wet_value) * self.feedback);
         out_value
     }
 }
@@ -131,7 +238,10 @@ const COMB_N: [usize; 8] = [1557, 1617, 1491, 1422, 1277, 1356, 1188, 1116];
 const ALLPASS_FB: f32 = 0.5;
 const ALLPASS_N: [usize; 4] = [225, 556, 441, 341];
 
-const INIT_SPREAD: usize = 23;
+/// Sample rate the line lengths, spread and modulation depth are tuned for
+const REFERENCE_SAMPLE_RATE: f64 = 44100.0;
+const INIT_SPREAD: f32 = 0.23;
+const MAX_SPREAD: usize = 100;
 const MOD_RANGE: usize = 20;
 const MOD_FREQ: f64 = 3.0;
 
@@ -147,13 +257,27 @@ struct Reverb {
     lfo_current_phase: f64,
     lfo_phase_step: f64,
 
-    combs: [(VecDeque<f32>, VecDeque<f32>); 8],
+    // Lengths scaled to the sample rate
+    comb_n: [usize; 8],
+    allpass_n: [usize; 4],
+    max_spread: usize,
+    mod_range: usize,
+
+    combs: [(DelayLine, DelayLine); 8],
     comb_state: [(f32, f32); 8],
-    allpass: [(VecDeque<f32>, VecDeque<f32>); 4],
+    allpass: [(DelayLine, DelayLine); 4],
 }
 
 impl Reverb {
     fn new(sample_rate: f64) -> Self {
+        let scale = |samples: usize| ((samples as f64 * sample_rate / REFERENCE_SAMPLE_RATE) as usize).max(1);
+        let comb_n = COMB_N.map(scale);
+        let allpass_n = ALLPASS_N.map(scale);
+        let max_spread = scale(MAX_SPREAD);
+        let mod_range = scale(MOD_RANGE);
+        // The right lines get the spread on top, the combs also swing half the mod range either way
+        let comb_line = |i: usize| DelayLine::new(comb_n[i] + max_spread + mod_range / 2);
+        let allpass_line = |i: usize| DelayLine::new(allpass_n[i] + max_spread);
         Self {
             wet1: 0.0,
             wet2: 0.0,
@@ -161,60 +285,72 @@ impl Reverb {
             width: 1.0,
             space: COMB_FB,
             damp: COMB_DAMP,
-            stereo_spread: INIT_SPREAD,
-            combs: std::array::from_fn(|i| (VecDeque::with_capacity(COMB_N[i] + MOD_RANGE), VecDeque::with_capacity(COMB_N[i] + MOD_RANGE))),
-            comb_state: [(0.0, 0.0); 8],
-            allpass: std::array::from_fn(|i| (VecDeque::with_capacity(ALLPASS_N[i]), VecDeque::with_capacity(ALLPASS_N[i]))),
+            stereo_spread: (INIT_SPREAD * max_spread as f32) as usize,
 
             lfo_current_phase: 0.0,
             lfo_phase_step: MOD_FREQ / sample_rate,
+
+            comb_n,
+            allpass_n,
+            max_spread,
+            mod_range,
+
+            combs: std::array::from_fn(|i| (comb_line(i), comb_line(i))),
+            comb_state: [(0.0, 0.0); 8],
+            allpass: std::array::from_fn(|i| (allpass_line(i), allpass_line(i))),
         }
     }
 
+    fn set_spread(&mut self, spread: f32) {
+        self.stereo_spread = (spread.clamp(0.0, 1.0) * self.max_spread as f32) as usize;
+    }
+
     #[inline(always)]
-    fn render(&mut self, input: f32) -> (f32, f32) {
-        let input_scaled = input / 16.0;
+    fn render(&mut self, left_input: f32, right_input: f32) -> (f32, f32) {
+        // The tank is fed mono, the dry signal keeps its stereo image
+        let input_scaled = (left_input + right_input) / 32.0;
         let mut out_l = 0.0;
         let mut out_r = 0.0;
 
         let lfo_value = 1.0 - 4.0 * (self.lfo_current_phase - (self.lfo_current_phase + 0.5).floor()).abs();
-        let mod_offset = (lfo_value * MOD_RANGE as f64 / 2.0) as isize;
+        let mod_offset = (lfo_value * self.mod_range as f64 / 2.0) as isize;
         self.lfo_current_phase = (self.lfo_current_phase + self.lfo_phase_step) % 1.0;
 
         for (i, (comb_l, comb_r)) in self.combs.iter_mut().enumerate() {
-            out_l += comb_process(input_scaled, comb_l, &mut self.comb_state[i].0, self.space, COMB_N[i], mod_offset, self.damp);
-            out_r += comb_process(input_scaled, comb_r, &mut self.comb_state[i].1, self.space, COMB_N[i] + self.stereo_spread, mod_offset, self.damp);
+            let n = self.comb_n[i];
+            out_l += comb_process(input_scaled, comb_l, &mut self.comb_state[i].0, self.space, n, mod_offset, self.damp);
+            out_r += comb_process(input_scaled, comb_r, &mut self.comb_state[i].1, self.space, n + self.stereo_spread, mod_offset, self.damp);
         }
 
         for (i, (allpass_l, allpass_r)) in self.allpass.iter_mut().enumerate() {
-            out_l = allpass_process(out_l, allpass_l, ALLPASS_FB, ALLPASS_N[i]);
-            out_r = allpass_process(out_r, allpass_r, ALLPASS_FB, ALLPASS_N[i] + self.stereo_spread);
+            let n = self.allpass_n[i];
+            out_l = allpass_process(out_l, allpass_l, ALLPASS_FB, n);
+            out_r = allpass_process(out_r, allpass_r, ALLPASS_FB, n + self.stereo_spread);
         }
 
-        let left = out_l * self.wet1 + out_r * self.wet2 + input * self.dry;
-        let right = out_r * self.wet1 + out_l * self.wet2 + input * self.dry;
+        let left = out_l * self.wet1 + out_r * self.wet2 + left_input * self.dry;
+        let right = out_r * self.wet1 + out_l * self.wet2 + right_input * self.dry;
 
         (left, right)
     }
 }
 
 #[inline]
-fn comb_process(input: f32, buf: &mut VecDeque<f32>, filter_state: &mut f32, fb: f32, n: usize, mod_offset: isize, d: f32) -> f32 {
-    let y_delayed = buf.get((n as isize - 1 + mod_offset) as usize).unwrap_or(&0.0);
+fn comb_process(input: f32, line: &mut DelayLine, filter_state: &mut f32, fb: f32, n: usize, mod_offset: isize, d: f32) -> f32 {
+    let y_delayed = line.read(n.saturating_add_signed(mod_offset).max(1));
     *filter_state = (1.0 - d) * y_delayed + d * *filter_state;
     let output = input + fb * *filter_state;
-    buf.remove(n - 1 + MOD_RANGE / 2);
-    buf.push_front(output);
+    line.write(output);
 
     output
 }
 
 #[inline]
-fn allpass_process(input: f32, buf: &mut VecDeque<f32>, fb: f32, n: usize) -> f32 {
-    let bufout = buf.remove(n - 1).unwrap_or_default();
+fn allpass_process(input: f32, line: &mut DelayLine, fb: f32, n: usize) -> f32 {
+    let bufout = line.read(n);
     let output = -fb *input + bufout;
 
-    buf.push_front(input + bufout * fb);
+    line.write(input + bufout * fb);
 
     output
-}
\ No newline at end of file
+}
@@ -1,6 +1,4 @@
-use std::time::Instant;
-
-use crate::audio::MAX_POLY_COUNT;
+use crate::audio::{module::Module, Block, MAX_POLY_COUNT};
 
 pub const GATE_INPUT: usize = 0 * MAX_POLY_COUNT;
 pub const VELOCITY_INPUT: usize = 1 * MAX_POLY_COUNT;
@@ -13,6 +11,11 @@ pub const TOTAL_INPUT_COUNT: usize = 6 * MAX_POLY_COUNT;
 pub const OUT_VALUE: usize = 0 * MAX_POLY_COUNT;
 pub const TOTAL_OUTPUT_COUNT: usize = 1 * MAX_POLY_COUNT;
 
+pub const ATTACK_PARAMETER: usize = 0;
+pub const DECAY_PARAMETER: usize = 1;
+pub const SUSTAIN_PARAMETER: usize = 2;
+pub const RELEASE_PARAMETER: usize = 3;
+
 pub const ENV_START_ATTACK: f32 = 0.02;
 pub const ENV_START_DECAY: f32 = 2.6;
 pub const ENV_START_SUSTAIN: f32 = 0.0;
@@ -21,14 +24,15 @@ pub const ENV_START_RELEASE: f32 = 2.0;
 const ENV_ADR_SCALING: f32 = 10.0;
 const SLIDER_EXP_RATIO: f32 = 2.0;
 
+/// Stage timings are counted in samples so the curve does not depend on buffer size or wall-clock time
 #[derive(Clone, Copy, Default)]
 struct EnvelopeMetaData {
-    start: Option<Instant>,
-    released: Option<Instant>,
+    start: Option<usize>,
+    released: Option<usize>,
     release_start_value: f32,
 }
 
-pub struct PolyEnvelope<const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> {
+pub struct PolyEnvelope {
     envelopes: [EnvelopeMetaData; MAX_POLY_COUNT],
     attack: f32,
     decay: f32,
@@ -36,7 +40,7 @@ pub struct PolyEnvelope<const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> {
     sustain: f32,
 }
 
-impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyEnvelope <INPUT_OFFSET, OUTPUT_OFFSET> {
+impl PolyEnvelope {
     pub fn new() -> Self {
         Self {
             envelopes: [EnvelopeMetaData::default(); MAX_POLY_COUNT],
@@ -62,59 +66,141 @@ impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyEnvelope <INPUT
     pub fn set_release_value(&mut self, release: f32) {
         self.release = release.powf(SLIDER_EXP_RATIO) * ENV_ADR_SCALING;
     }
+}
+
+impl Module for PolyEnvelope {
+    fn input_count(&self) -> usize {
+        TOTAL_INPUT_COUNT
+    }
+
+    fn output_count(&self) -> usize {
+        TOTAL_OUTPUT_COUNT
+    }
 
-    #[inline(always)]
-    pub fn render(&mut self, inputs: &[f32], outputs: &mut [f32]) {
+    fn set_parameter(&mut self, parameter: usize, value: f32) {
+        match parameter {
+            ATTACK_PARAMETER => self.set_attack_value(value),
+            DECAY_PARAMETER => self.set_decay_value(value),
+            SUSTAIN_PARAMETER => self.set_sustain_value(value),
+            RELEASE_PARAMETER => self.set_release_value(value),
+            _ => {},
+        }
+    }
+
+    fn render(&mut self, inputs: &[Block], outputs: &mut [Block], len: usize, sample_rate: f64) {
+        let sample_rate = sample_rate as f32;
         for (envelope, meta) in self.envelopes.iter_mut().enumerate() {
-            let velocity = inputs[INPUT_OFFSET + VELOCITY_INPUT + envelope];
-            let gate = inputs[INPUT_OFFSET + GATE_INPUT + envelope];
-            let attack = self.attack + inputs[INPUT_OFFSET + ATTACK_INPUT + envelope] * ENV_ADR_SCALING;
-            let decay = self.decay + inputs[INPUT_OFFSET + DECAY_INPUT + envelope] * ENV_ADR_SCALING;
-            let sustain = self.sustain + inputs[INPUT_OFFSET + SUSTAIN_INPUT + envelope];
-            let release = self.release + inputs[INPUT_OFFSET + RELEASE_INPUT + envelope] * ENV_ADR_SCALING;
-
-            let raw = if let Some(start_time) = meta.start {
-                let elapsed = start_time.elapsed().as_secs_f32();
-                if elapsed < attack {
-                    1.0 * elapsed / attack
-                } else if elapsed - attack < decay {
-                    let since_decay = elapsed - attack;
-                    let peak_sustain_delta = 1.0 - sustain;
-
-                    1.0 - peak_sustain_delta * since_decay / decay
-                } else {
-                    sustain
-                }
-            } else if let Some(released_time) = meta.released {
-                let elapsed = released_time.elapsed().as_secs_f32();
-                let elapsed_ratio = elapsed / release;
+            let velocity_inputs = &inputs[VELOCITY_INPUT + envelope];
+            let gate_inputs = &inputs[GATE_INPUT + envelope];
+            let attack_inputs = &inputs[ATTACK_INPUT + envelope];
+            let decay_inputs = &inputs[DECAY_INPUT + envelope];
+            let sustain_inputs = &inputs[SUSTAIN_INPUT + envelope];
+            let release_inputs = &inputs[RELEASE_INPUT + envelope];
+            let output = &mut outputs[OUT_VALUE + envelope];
+
+            for i in 0..len {
+                let velocity = velocity_inputs[i];
+                let gate = gate_inputs[i];
+                let attack = self.attack + attack_inputs[i] * ENV_ADR_SCALING;
+                let decay = self.decay + decay_inputs[i] * ENV_ADR_SCALING;
+                let sustain = self.sustain + sustain_inputs[i];
+                let release = self.release + release_inputs[i] * ENV_ADR_SCALING;
+
+                let raw = if let Some(start_samples) = meta.start {
+                    let elapsed = start_samples as f32 / sample_rate;
+                    if elapsed < attack {
+                        1.0 * elapsed / attack
+                    } else if elapsed - attack < decay {
+                        let since_decay = elapsed - attack;
+                        let peak_sustain_delta = 1.0 - sustain;
+
+                        1.0 - peak_sustain_delta * since_decay / decay
+                    } else {
+                        sustain
+                    }
+                } else if let Some(released_samples) = meta.released {
+                    let elapsed = released_samples as f32 / sample_rate;
+                    let elapsed_ratio = elapsed / release;
         
-                if elapsed_ratio < 1.0 {
-                    meta.release_start_value * (1.0 - (elapsed_ratio).powf(0.4))
+                    if elapsed_ratio < 1.0 {
+                        meta.release_start_value * (1.0 - (elapsed_ratio).powf(0.4))
+                    } else {
+                        meta.released = None;
+                        0.0
+                    }
                 } else {
-                    meta.released = None;
                     0.0
-                }
-            } else {
-                0.0
-            };
-
-            if gate > 0.0 {
-                if let None = meta.start {
-                    meta.start = Some(Instant::now());
-                    meta.released = None;
-                }
-            } else {
-                if let None = meta.released {
-                    if let Some(_) = meta.start {
-                        meta.start = None;
-                        meta.released = Some(Instant::now());
-                        meta.release_start_value = raw;
+                };
+
+                meta.start = meta.start.map(|samples| samples + 1);
+                meta.released = meta.released.map(|samples| samples + 1);
+
+                if gate > 0.0 {
+                    if let None = meta.start {
+                        meta.start = Some(0);
+                        meta.released = None;
+                    }
+                } else {
+                    if let None = meta.released {
+                        if let Some(_) = meta.start {
+                            meta.start = None;
+                            meta.released = Some(0);
+                            meta.release_start_value = raw;
+                        }
                     }
                 }
+
+                output[i] = raw * velocity;
             }
+        }
+    }
+}
+
 
-            outputs[OUTPUT_OFFSET + OUT_VALUE + envelope] = raw * velocity;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::BLOCK_SIZE;
+
+    /// Renders voice 0 with the gate held for `gate_samples`, then released for as long again
+    fn render_voice(sample_rate: f64, gate_samples: usize) -> Vec<f32> {
+        let mut envelope = PolyEnvelope::new();
+        // Each stage lasts 0.1 ^ 2 * 10 = 0.1 seconds
+        envelope.set_attack_value(0.1);
+        envelope.set_decay_value(0.1);
+        envelope.set_sustain_value(0.5);
+        envelope.set_release_value(0.1);
+
+        let mut inputs = vec![[0.0; BLOCK_SIZE]; TOTAL_INPUT_COUNT];
+        let mut outputs = vec![[0.0; BLOCK_SIZE]; TOTAL_OUTPUT_COUNT];
+        inputs[VELOCITY_INPUT] = [1.0; BLOCK_SIZE];
+        let mut rendered = Vec::new();
+        while rendered.len() < 2 * gate_samples {
+            for (i, gate) in inputs[GATE_INPUT].iter_mut().enumerate() {
+                *gate = if rendered.len() + i < gate_samples { 1.0 } else { 0.0 };
+            }
+            envelope.render(&inputs, &mut outputs, BLOCK_SIZE, sample_rate);
+            rendered.extend_from_slice(&outputs[OUT_VALUE]);
         }
+        rendered
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn stage_lengths_follow_the_sample_rate() {
+        for sample_rate in [44100.0, 96000.0] {
+            let stage = (0.1 * sample_rate) as usize;
+            let gate_samples = 3 * stage;
+            let rendered = render_voice(sample_rate, gate_samples);
+
+            let loudest = rendered.iter().cloned().fold(0.0, f32::max);
+            let peak = rendered.iter().position(|&value| value == loudest).unwrap();
+            assert!(loudest > 0.999, "attack peaked at {loudest}");
+            let sustain = rendered.iter().position(|&value| value == 0.5).unwrap();
+            let silent = gate_samples + rendered[gate_samples..].iter().position(|&value| value == 0.0).unwrap();
+            // The gate is read one sample before the curve starts moving
+            assert!(peak.abs_diff(stage + 1) <= 1, "attack took {peak} samples at {sample_rate} Hz");
+            assert!((sustain - peak).abs_diff(stage) <= 1, "decay took {} samples at {sample_rate} Hz", sustain - peak);
+            assert!((silent - gate_samples).abs_diff(stage) <= 2, "release took {} samples at {sample_rate} Hz", silent - gate_samples);
+        }
+    }
+}
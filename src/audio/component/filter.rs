@@ -1,30 +1,109 @@
-use crate::audio::MAX_POLY_COUNT;
+use crate::audio::{component::CutoffTable, module::Module, Block, MAX_POLY_COUNT};
 
 pub const VALUE_INPUT: usize = 0 * MAX_POLY_COUNT;
 pub const FREQUENCY_INPUT: usize = 1 * MAX_POLY_COUNT;
-pub const TOTAL_INPUT_COUNT: usize = 2 * MAX_POLY_COUNT;
+pub const RESONANCE_INPUT: usize = 2 * MAX_POLY_COUNT;
+/// Note of the voice, normalled to the MIDI note on the panel filters
+pub const KEYTRACK_INPUT: usize = 3 * MAX_POLY_COUNT;
+/// Stereo side of the value, filtered with the value's cutoff but never self-oscillating
+pub const SIDE_INPUT: usize = 4 * MAX_POLY_COUNT;
+pub const TOTAL_INPUT_COUNT: usize = 5 * MAX_POLY_COUNT;
 
 pub const VALUE_OUTPUT: usize = 0 * MAX_POLY_COUNT;
-pub const TOTAL_OUTPUT_COUNT: usize = 1 * MAX_POLY_COUNT;
+pub const SIDE_OUTPUT: usize = 1 * MAX_POLY_COUNT;
+pub const TOTAL_OUTPUT_COUNT: usize = 2 * MAX_POLY_COUNT;
 
+pub const FREQUENCY_PARAMETER: usize = 0;
+pub const RESONANCE_PARAMETER: usize = 1;
+pub const KEYTRACK_PARAMETER: usize = 2;
+/// Bipolar gain of the frequency input, 0.5 ignores it and 1.0 passes it as is
+pub const CUTOFF_AMOUNT_PARAMETER: usize = 3;
+
+/// Note the cutoff knob is set for, keytracked cutoffs move away from it with the played note
+const KEYTRACK_CENTER: f32 = 60.0 / 128.0;
+
+/// Response the state variable filter sends to `VALUE_OUTPUT`
+#[derive(Clone, Copy, Debug, Default)]
+pub enum FilterMode {
+    #[default]
+    Lowpass,
+    Highpass,
+    Bandpass,
+    Notch,
+    Peak,
+}
+
+/// Damping at zero resonance, gives the flat Butterworth response
+const BUTTERWORTH_DAMPING: f32 = std::f32::consts::SQRT_2;
+/// Damping at full resonance, below zero so the filter rings up into a sine on its own
+const SELF_OSCILLATION_DAMPING: f32 = -0.02;
+/// Rough level a self-oscillating filter settles at
+const SELF_OSCILLATION_LEVEL: f32 = 0.5;
+/// Damping added per squared unit of band state, cancels the negative damping at `SELF_OSCILLATION_LEVEL`
+const SELF_OSCILLATION_LIMITING: f32 = -2.0 * SELF_OSCILLATION_DAMPING / (SELF_OSCILLATION_LEVEL * SELF_OSCILLATION_LEVEL);
+
+/// Summed state at or below which an idle voice has rung out, it stops ticking and its state is cleared
+const SETTLED_LEVEL: f32 = 1e-6;
+
+/// Integrator states of the state variable filter
 #[derive(Clone, Copy, Default)]
 struct BufferData {
-    x_minus: f32,
-    x_minus2: f32,
-    y_minus: f32,
-    y_minus2: f32,
+    band: f32,
+    low: f32,
 }
 
-pub struct PolyFilter<const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> {
+impl BufferData {
+    fn is_settled(&self) -> bool {
+        self.band.abs() + self.low.abs() <= SETTLED_LEVEL
+    }
+
+    /// Zero delay feedback state variable filter, see Simper's "Linear Trapezoidal Integrated SVF"
+    #[inline(always)]
+    fn tick(&mut self, input: f32, g: f32, damping: f32, mode: FilterMode) -> f32 {
+        let a1 = 1.0 / (1.0 + g * (g + damping));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let v3 = input - self.low;
+        let band = a1 * self.band + a2 * v3;
+        let low = self.low + a2 * self.band + a3 * v3;
+
+        self.band = 2.0 * band - self.band;
+        self.low = 2.0 * low - self.low;
+
+        let high = input - damping * band - low;
+        match mode {
+            FilterMode::Lowpass => low,
+            FilterMode::Highpass => high,
+            FilterMode::Bandpass => band,
+            FilterMode::Notch => low + high,
+            FilterMode::Peak => low - high,
+        }
+    }
+}
+
+pub struct PolyFilter {
     frequency: f32,
+    resonance: f32,
+    keytrack: f32,
+    cutoff_amount: f32,
+    mode: FilterMode,
+    cutoff_table: CutoffTable,
     buffers: [BufferData; MAX_POLY_COUNT],
+    side_buffers: [BufferData; MAX_POLY_COUNT],
 }
 
-impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyFilter <INPUT_OFFSET, OUTPUT_OFFSET> {
+impl PolyFilter {
     pub fn new() -> Self {
         Self {
             frequency: 0.7,
+            resonance: 0.0,
+            keytrack: 0.0,
+            cutoff_amount: 1.0,
+            mode: FilterMode::default(),
+            cutoff_table: CutoffTable::new(),
             buffers: [BufferData::default(); MAX_POLY_COUNT],
+            side_buffers: [BufferData::default(); MAX_POLY_COUNT],
         }
     }
 
@@ -32,35 +111,105 @@ impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyFilter <INPUT_O
         self.frequency = freq;
     }
 
-    #[inline(always)]
-    pub fn render(&mut self, inputs: &[f32], outputs: &mut [f32], sample_rate: f32) {
-        for (filter, buffer) in self.buffers.iter_mut().enumerate() {
-            let value_input = inputs[INPUT_OFFSET + VALUE_INPUT + filter];
+    pub fn set_resonance_value(&mut self, resonance: f32) {
+        self.resonance = resonance;
+    }
+
+    pub fn set_keytrack_value(&mut self, keytrack: f32) {
+        self.keytrack = keytrack;
+    }
+
+    pub fn set_cutoff_amount_value(&mut self, amount: f32) {
+        self.cutoff_amount = 2.0 * amount - 1.0;
+    }
 
-            if value_input != 0.0 {
-                let frequency_input = inputs[INPUT_OFFSET + FREQUENCY_INPUT + filter];
+    pub fn set_mode(&mut self, mode: FilterMode) {
+        self.mode = mode;
+    }
+}
 
-                let voltage = (self.frequency + frequency_input).min(1.0).max(0.0);
-                let frequency = super::calculate_freq(voltage);
+impl Module for PolyFilter {
+    fn input_count(&self) -> usize {
+        TOTAL_INPUT_COUNT
+    }
 
-                let c = 1.0 / (std::f32::consts::PI * frequency / sample_rate).tan();
-                let a0 = 1.0 / (1.0 + 2.0_f32.sqrt() * c + c * c);
-                let a1 = 2.0 * a0;
-                let a2 = a0;
-                let b1 = 2.0 * a0 * (1.0 - c * c);
-                let b2 = a0 * (1.0 - 2.0_f32.sqrt() * c + c * c);
+    fn output_count(&self) -> usize {
+        TOTAL_OUTPUT_COUNT
+    }
 
-                let output_value = value_input * a0 + buffer.x_minus * a1 + buffer.x_minus2 * a2 - buffer.y_minus * b1 - buffer.y_minus2 * b2;
+    fn side_output(&self, jack: usize) -> Option<usize> {
+        (jack == VALUE_OUTPUT).then_some(SIDE_OUTPUT)
+    }
 
-                buffer.x_minus2 = buffer.x_minus;
-                buffer.x_minus = value_input;
-                buffer.y_minus2 = buffer.y_minus;
-                buffer.y_minus = output_value;
+    fn side_input(&self, jack: usize) -> Option<usize> {
+        (jack == VALUE_INPUT).then_some(SIDE_INPUT)
+    }
 
-                outputs[OUTPUT_OFFSET + VALUE_OUTPUT + filter] = output_value;
-            } else {
-                outputs[OUTPUT_OFFSET + VALUE_OUTPUT + filter] = 0.0;
+    fn set_parameter(&mut self, parameter: usize, value: f32) {
+        match parameter {
+            FREQUENCY_PARAMETER => self.set_freq_value(value),
+            RESONANCE_PARAMETER => self.set_resonance_value(value),
+            KEYTRACK_PARAMETER => self.set_keytrack_value(value),
+            CUTOFF_AMOUNT_PARAMETER => self.set_cutoff_amount_value(value),
+            _ => {},
+        }
+    }
+
+    fn render(&mut self, inputs: &[Block], outputs: &mut [Block], len: usize, sample_rate: f64) {
+        self.cutoff_table.prepare(sample_rate as f32);
+        let voices = self.buffers.iter_mut().zip(self.side_buffers.iter_mut());
+        for (filter, (buffer, side_buffer)) in voices.enumerate() {
+            let value_inputs = &inputs[VALUE_INPUT + filter];
+            let frequency_inputs = &inputs[FREQUENCY_INPUT + filter];
+            let resonance_inputs = &inputs[RESONANCE_INPUT + filter];
+            let keytrack_inputs = &inputs[KEYTRACK_INPUT + filter];
+            let side_inputs = &inputs[SIDE_INPUT + filter];
+            let [output, side_output] = outputs
+                .get_disjoint_mut([VALUE_OUTPUT + filter, SIDE_OUTPUT + filter])
+                .unwrap();
+
+            for i in 0..len {
+                let value_input = value_inputs[i];
+                let side_input = side_inputs[i];
+
+                let resonance = (self.resonance + resonance_inputs[i]).min(1.0).max(0.0);
+                let mut damping = BUTTERWORTH_DAMPING + (SELF_OSCILLATION_DAMPING - BUTTERWORTH_DAMPING) * resonance;
+                let self_oscillating = damping < 0.0;
+
+                // Voices keep ticking through silent input until they ring out, then stay cleared until the next one
+                let value_active = value_input != 0.0 || !buffer.is_settled();
+                let side_active = side_input != 0.0 || !side_buffer.is_settled();
+                if !value_active && !side_active {
+                    *buffer = BufferData::default();
+                    *side_buffer = BufferData::default();
+                    output[i] = 0.0;
+                    side_output[i] = 0.0;
+                    continue;
+                }
+
+                // A voltage step is a semitone step in `calculate_freq`, so full keytrack follows the note exactly
+                let keytrack = self.keytrack * (keytrack_inputs[i] - KEYTRACK_CENTER);
+                let g = self.cutoff_table.lookup(self.frequency + self.cutoff_amount * frequency_inputs[i] + keytrack);
+
+                if self_oscillating {
+                    // Negative damping alone grows without bound, this settles it into a steady sine
+                    damping += SELF_OSCILLATION_LIMITING * buffer.band * buffer.band;
+                }
+
+                output[i] = if value_active {
+                    buffer.tick(value_input, g, damping, self.mode)
+                } else {
+                    *buffer = BufferData::default();
+                    0.0
+                };
+                // Same response as filtering left and right on their own, short of the self-oscillation
+                side_output[i] = if side_active {
+                    side_buffer.tick(side_input, g, damping.max(0.0), self.mode)
+                } else {
+                    *side_buffer = BufferData::default();
+                    0.0
+                };
             }
         }
     }
-}
\ No newline at end of file
+}
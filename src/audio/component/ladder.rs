@@ -0,0 +1,246 @@
+use crate::audio::{component::CutoffTable, module::Module, Block, MAX_POLY_COUNT};
+
+pub const VALUE_INPUT: usize = 0 * MAX_POLY_COUNT;
+pub const FREQUENCY_INPUT: usize = 1 * MAX_POLY_COUNT;
+pub const RESONANCE_INPUT: usize = 2 * MAX_POLY_COUNT;
+pub const DRIVE_INPUT: usize = 3 * MAX_POLY_COUNT;
+/// Stereo side of the value, it saturates on its own instead of with the value and never self-oscillates
+pub const SIDE_INPUT: usize = 4 * MAX_POLY_COUNT;
+pub const TOTAL_INPUT_COUNT: usize = 5 * MAX_POLY_COUNT;
+
+pub const VALUE_OUTPUT: usize = 0 * MAX_POLY_COUNT;
+pub const SIDE_OUTPUT: usize = 1 * MAX_POLY_COUNT;
+pub const TOTAL_OUTPUT_COUNT: usize = 2 * MAX_POLY_COUNT;
+
+pub const FREQUENCY_PARAMETER: usize = 0;
+pub const RESONANCE_PARAMETER: usize = 1;
+pub const DRIVE_PARAMETER: usize = 2;
+
+/// Feedback at full resonance, the ladder starts to self-oscillate just above 4
+const MAX_FEEDBACK: f32 = 4.05;
+const SELF_OSCILLATION_FEEDBACK: f32 = 4.0;
+/// Share of the passband the feedback takes away that is made up on the output
+const RESONANCE_COMPENSATION: f32 = 0.5;
+/// Input gain at full drive
+const MAX_DRIVE: f32 = 10.0;
+
+/// Summed state at or below which an idle voice has rung out, it stops ticking and its state is cleared
+const SETTLED_LEVEL: f32 = 1e-6;
+
+/// Integrator states of the four one pole stages
+#[derive(Clone, Copy, Default)]
+struct BufferData {
+    stages: [f32; 4],
+    output: f32,
+}
+
+impl BufferData {
+    fn is_settled(&self) -> bool {
+        self.stages.iter().fold(self.output.abs(), |sum, state| sum + state.abs()) <= SETTLED_LEVEL
+    }
+
+    #[inline(always)]
+    fn tick(&mut self, input: f32, gain: f32, feedback: f32) -> f32 {
+        // Feedback is taken from the last output, the one sample delay keeps the loop explicit
+        let mut stage_value = input - feedback * self.output;
+        for state in self.stages.iter_mut() {
+            let v = (stage_value.tanh() - *state) * gain;
+            stage_value = v + *state;
+            *state = stage_value + v;
+        }
+        self.output = stage_value;
+
+        stage_value * (1.0 + RESONANCE_COMPENSATION * feedback)
+    }
+}
+
+/// Four saturating one pole lowpass stages in a feedback loop, the 24 dB/oct Moog ladder
+pub struct PolyLadder {
+    frequency: f32,
+    resonance: f32,
+    drive: f32,
+    cutoff_table: CutoffTable,
+    buffers: [BufferData; MAX_POLY_COUNT],
+    side_buffers: [BufferData; MAX_POLY_COUNT],
+}
+
+impl PolyLadder {
+    pub fn new() -> Self {
+        Self {
+            frequency: 0.7,
+            resonance: 0.0,
+            drive: 0.0,
+            cutoff_table: CutoffTable::new(),
+            buffers: [BufferData::default(); MAX_POLY_COUNT],
+            side_buffers: [BufferData::default(); MAX_POLY_COUNT],
+        }
+    }
+
+    pub fn set_freq_value(&mut self, freq: f32) {
+        self.frequency = freq;
+    }
+
+    pub fn set_resonance_value(&mut self, resonance: f32) {
+        self.resonance = resonance;
+    }
+
+    pub fn set_drive_value(&mut self, drive: f32) {
+        self.drive = drive;
+    }
+}
+
+impl Module for PolyLadder {
+    fn input_count(&self) -> usize {
+        TOTAL_INPUT_COUNT
+    }
+
+    fn output_count(&self) -> usize {
+        TOTAL_OUTPUT_COUNT
+    }
+
+    fn side_output(&self, jack: usize) -> Option<usize> {
+        (jack == VALUE_OUTPUT).then_some(SIDE_OUTPUT)
+    }
+
+    fn side_input(&self, jack: usize) -> Option<usize> {
+        (jack == VALUE_INPUT).then_some(SIDE_INPUT)
+    }
+
+    fn set_parameter(&mut self, parameter: usize, value: f32) {
+        match parameter {
+            FREQUENCY_PARAMETER => self.set_freq_value(value),
+            RESONANCE_PARAMETER => self.set_resonance_value(value),
+            DRIVE_PARAMETER => self.set_drive_value(value),
+            _ => {},
+        }
+    }
+
+    fn render(&mut self, inputs: &[Block], outputs: &mut [Block], len: usize, sample_rate: f64) {
+        self.cutoff_table.prepare(sample_rate as f32);
+        let voices = self.buffers.iter_mut().zip(self.side_buffers.iter_mut());
+        for (filter, (buffer, side_buffer)) in voices.enumerate() {
+            let value_inputs = &inputs[VALUE_INPUT + filter];
+            let frequency_inputs = &inputs[FREQUENCY_INPUT + filter];
+            let resonance_inputs = &inputs[RESONANCE_INPUT + filter];
+            let drive_inputs = &inputs[DRIVE_INPUT + filter];
+            let side_inputs = &inputs[SIDE_INPUT + filter];
+            let [output, side_output] = outputs
+                .get_disjoint_mut([VALUE_OUTPUT + filter, SIDE_OUTPUT + filter])
+                .unwrap();
+
+            for i in 0..len {
+                let value_input = value_inputs[i];
+                let side_input = side_inputs[i];
+
+                let resonance = (self.resonance + resonance_inputs[i]).min(1.0).max(0.0);
+                let feedback = MAX_FEEDBACK * resonance;
+
+                // Voices keep ticking through silent input until they ring out, then stay cleared until the next one
+                let value_active = value_input != 0.0 || !buffer.is_settled();
+                let side_active = side_input != 0.0 || !side_buffer.is_settled();
+                if !value_active && !side_active {
+                    *buffer = BufferData::default();
+                    *side_buffer = BufferData::default();
+                    output[i] = 0.0;
+                    side_output[i] = 0.0;
+                    continue;
+                }
+
+                let drive = 1.0 + (MAX_DRIVE - 1.0) * (self.drive + drive_inputs[i]).min(1.0).max(0.0);
+                let g = self.cutoff_table.lookup(self.frequency + frequency_inputs[i]);
+                let gain = g / (1.0 + g);
+
+                output[i] = if value_active {
+                    buffer.tick(drive * value_input, gain, feedback)
+                } else {
+                    *buffer = BufferData::default();
+                    0.0
+                };
+                side_output[i] = if side_active {
+                    side_buffer.tick(drive * side_input, gain, feedback.min(SELF_OSCILLATION_FEEDBACK))
+                } else {
+                    *side_buffer = BufferData::default();
+                    0.0
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::BLOCK_SIZE;
+
+    /// Renders voice 0 of `ladder` fed with `input(sample)`
+    fn render_voice(ladder: &mut PolyLadder, samples: usize, sample_rate: f64, input: impl Fn(usize) -> f32) -> Vec<f32> {
+        let mut inputs = vec![[0.0; BLOCK_SIZE]; TOTAL_INPUT_COUNT];
+        let mut outputs = vec![[0.0; BLOCK_SIZE]; TOTAL_OUTPUT_COUNT];
+        let mut rendered = Vec::with_capacity(samples);
+        while rendered.len() < samples {
+            for (i, value) in inputs[VALUE_INPUT].iter_mut().enumerate() {
+                *value = input(rendered.len() + i);
+            }
+            ladder.render(&inputs, &mut outputs, BLOCK_SIZE, sample_rate);
+            rendered.extend_from_slice(&outputs[VALUE_OUTPUT]);
+        }
+        rendered.truncate(samples);
+        rendered
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|sample| sample * sample).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn stays_bounded_at_full_resonance_and_drive() {
+        // Every stage saturates through `tanh`, so only the resonance compensation can lift the output past 1
+        let bound = 1.0 + RESONANCE_COMPENSATION * MAX_FEEDBACK;
+        for frequency in [0.3, 0.7, 1.0] {
+            let mut ladder = PolyLadder::new();
+            ladder.set_freq_value(frequency);
+            ladder.set_resonance_value(1.0);
+            ladder.set_drive_value(1.0);
+            let square = |i: usize| if i % 480 < 240 { 1.0 } else { -1.0 };
+            let rendered = render_voice(&mut ladder, 96000, 48000.0, square);
+            assert!(rendered.iter().all(|sample| sample.is_finite() && sample.abs() <= bound), "cutoff {frequency} ran away");
+        }
+    }
+
+    #[test]
+    fn self_oscillates_above_a_feedback_of_4() {
+        let ring = |feedback: f32| {
+            let mut ladder = PolyLadder::new();
+            ladder.set_freq_value(0.5);
+            ladder.set_resonance_value(feedback / MAX_FEEDBACK);
+            let impulse = |i: usize| if i == 0 { 0.1 } else { 0.0 };
+            let rendered = render_voice(&mut ladder, 3 * 48000, 48000.0, impulse);
+            (rms(&rendered[48000..2 * 48000]), rms(&rendered[2 * 48000..]))
+        };
+
+        // Rings up from a single impulse and holds a steady level seconds later
+        let (earlier, later) = ring(MAX_FEEDBACK);
+        assert!(later > 0.1, "oscillation died out at {later}");
+        assert!((later / earlier - 1.0).abs() < 0.01, "oscillation still changing from {earlier} to {later}");
+
+        let (_, later) = ring(3.5);
+        assert_eq!(later, 0.0);
+    }
+
+    #[test]
+    fn rolls_off_24_db_per_octave_above_cutoff() {
+        let sample_rate = 96000.0;
+        let level = |frequency: f64| {
+            let mut ladder = PolyLadder::new();
+            // 440 Hz, three octaves below the first measured tone
+            ladder.set_freq_value(69.0 / 128.0);
+            // Quiet enough that the `tanh` stages stay linear
+            let sine = |i: usize| 0.01 * (std::f64::consts::TAU * frequency * i as f64 / sample_rate).sin() as f32;
+            let rendered = render_voice(&mut ladder, 96000, sample_rate, sine);
+            20.0 * rms(&rendered[48000..]).log10()
+        };
+
+        let slope = level(7040.0) - level(3520.0);
+        assert!((slope + 24.0).abs() < 1.5, "slope was {slope} dB/oct");
+    }
+}
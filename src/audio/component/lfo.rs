@@ -1,4 +1,4 @@
-use crate::audio::MAX_POLY_COUNT;
+use crate::audio::{module::Module, Block, MAX_POLY_COUNT};
 use super::WaveShape;
 
 pub const TOTAL_INPUT_COUNT: usize = 0 * MAX_POLY_COUNT;
@@ -6,14 +6,17 @@ pub const TOTAL_INPUT_COUNT: usize = 0 * MAX_POLY_COUNT;
 pub const OUT_VALUE: usize = 0 * MAX_POLY_COUNT;
 pub const TOTAL_OUTPUT_COUNT: usize = 1 * MAX_POLY_COUNT;
 
-pub struct PolyLfo<const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> {
+pub const FREQUENCY_PARAMETER: usize = 0;
+pub const PHASE_PARAMETER: usize = 1;
+
+pub struct PolyLfo {
     shape: WaveShape,
     phase: f64,
     frequency: f64,
     current_phases: [f64; MAX_POLY_COUNT],
 }
 
-impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyLfo <INPUT_OFFSET, OUTPUT_OFFSET> {
+impl PolyLfo {
     pub fn new() -> Self {
         Self {
             shape: WaveShape::default(),
@@ -31,26 +34,45 @@ impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyLfo <INPUT_OFFS
         self.frequency = (freq as f64).powf(3.0) * 99.9 + 0.1;
     }
 
-    #[allow(dead_code)]
     pub fn set_phase_value(&mut self, phase: f32) {
         self.phase = phase as f64;
     }
+}
+
+impl Module for PolyLfo {
+    fn input_count(&self) -> usize {
+        TOTAL_INPUT_COUNT
+    }
+
+    fn output_count(&self) -> usize {
+        TOTAL_OUTPUT_COUNT
+    }
 
-    #[inline(always)]
-    pub fn render(&mut self, _inputs: &[f32], outputs: &mut [f32], sample_rate: f64) {
+    fn set_parameter(&mut self, parameter: usize, value: f32) {
+        match parameter {
+            FREQUENCY_PARAMETER => self.set_freq_value(value),
+            PHASE_PARAMETER => self.set_phase_value(value),
+            _ => {},
+        }
+    }
+
+    fn render(&mut self, _inputs: &[Block], outputs: &mut [Block], len: usize, sample_rate: f64) {
+        let phase_increment = self.frequency / sample_rate;
         for (lfo, current_phase) in self.current_phases.iter_mut().enumerate() {
-            let phase_increment = self.frequency / sample_rate;
-            let phase = *current_phase;
-
-            let raw = match self.shape {
-                WaveShape::Saw => 2.0 * phase - 1.0,
-                WaveShape::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
-                WaveShape::Square => if phase < 0.5 {1.0} else {-1.0},
-                WaveShape::Triangle => 1.0 - 4.0 * (phase - (phase + 0.5).floor()).abs(),
-            };
-
-            *current_phase = (*current_phase + phase_increment) % 1.0;
-            outputs[OUTPUT_OFFSET + OUT_VALUE + lfo] = raw as f32 / 16.0;
+            let output = &mut outputs[OUT_VALUE + lfo];
+            for i in 0..len {
+                let phase = *current_phase;
+
+                let raw = match self.shape {
+                    WaveShape::Saw => 2.0 * phase - 1.0,
+                    WaveShape::Sine => (2.0 * std::f64::consts::PI * phase).sin(),
+                    WaveShape::Square => if phase < 0.5 {1.0} else {-1.0},
+                    WaveShape::Triangle => 1.0 - 4.0 * (phase - (phase + 0.5).floor()).abs(),
+                };
+
+                *current_phase = (*current_phase + phase_increment) % 1.0;
+                output[i] = raw as f32 / 16.0;
+            }
         }
     }
-}
\ No newline at end of file
+}
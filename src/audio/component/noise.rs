@@ -0,0 +1,213 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::audio::{module::Module, Block, BLOCK_SIZE, MAX_POLY_COUNT};
+
+pub const AMP_INPUT: usize = 0 * MAX_POLY_COUNT;
+pub const TOTAL_INPUT_COUNT: usize = 1 * MAX_POLY_COUNT;
+
+pub const WHITE_OUTPUT: usize = 0 * MAX_POLY_COUNT;
+pub const PINK_OUTPUT: usize = 1 * MAX_POLY_COUNT;
+pub const BROWN_OUTPUT: usize = 2 * MAX_POLY_COUNT;
+pub const TOTAL_OUTPUT_COUNT: usize = 3 * MAX_POLY_COUNT;
+
+pub const LEVEL_PARAMETER: usize = 0;
+
+/// Brings pink noise back to about the level of the white noise it is filtered from
+const PINK_GAIN: f32 = 0.11;
+/// Share of the white noise the brown integrator takes in each sample, also how fast it leaks back to 0
+const BROWN_STEP: f32 = 0.02;
+const BROWN_GAIN: f32 = 3.5;
+
+/// Filter states of one voice
+#[derive(Clone, Copy, Default)]
+struct ColourData {
+    pink: [f32; 7],
+    brown: f32,
+}
+
+impl ColourData {
+    /// Paul Kellet's refined filter, -3 dB/oct to within half a dB above 10 Hz
+    #[inline(always)]
+    fn pink(&mut self, white: f32) -> f32 {
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * PINK_GAIN
+    }
+
+    /// Leaky integrator, -6 dB/oct without wandering off
+    #[inline(always)]
+    fn brown(&mut self, white: f32) -> f32 {
+        self.brown = (self.brown + BROWN_STEP * white) / (1.0 + BROWN_STEP);
+        self.brown * BROWN_GAIN
+    }
+}
+
+/// White, pink and brown noise, every voice gets its own stream unless they are shared
+pub struct PolyNoise {
+    level: f32,
+    shared: bool,
+    rng: StdRng,
+    colours: [ColourData; MAX_POLY_COUNT],
+    /// First voice's white, pink and brown values, what the others play while shared
+    shared_block: [(f32, f32, f32); BLOCK_SIZE],
+}
+
+impl PolyNoise {
+    pub fn new() -> Self {
+        Self {
+            level: 0.5,
+            shared: false,
+            rng: StdRng::from_entropy(),
+            colours: [ColourData::default(); MAX_POLY_COUNT],
+            shared_block: [(0.0, 0.0, 0.0); BLOCK_SIZE],
+        }
+    }
+
+    pub fn set_level_value(&mut self, level: f32) {
+        self.level = level;
+    }
+
+    pub fn set_shared(&mut self, shared: bool) {
+        self.shared = shared;
+    }
+}
+
+impl Module for PolyNoise {
+    fn input_count(&self) -> usize {
+        TOTAL_INPUT_COUNT
+    }
+
+    fn output_count(&self) -> usize {
+        TOTAL_OUTPUT_COUNT
+    }
+
+    fn set_parameter(&mut self, parameter: usize, value: f32) {
+        if parameter == LEVEL_PARAMETER {
+            self.set_level_value(value);
+        }
+    }
+
+    fn render(&mut self, inputs: &[Block], outputs: &mut [Block], len: usize, _sample_rate: f64) {
+        for (noise, colours) in self.colours.iter_mut().enumerate() {
+            let amp_inputs = &inputs[AMP_INPUT + noise];
+            let [white_output, pink_output, brown_output] = outputs
+                .get_disjoint_mut([WHITE_OUTPUT + noise, PINK_OUTPUT + noise, BROWN_OUTPUT + noise])
+                .unwrap();
+
+            for i in 0..len {
+                let (white, pink, brown) = if self.shared && noise != 0 {
+                    self.shared_block[i]
+                } else {
+                    let white = self.rng.gen_range(-1.0..1.0);
+                    let values = (white, colours.pink(white), colours.brown(white));
+                    self.shared_block[i] = values;
+                    values
+                };
+
+                let gain = self.level * amp_inputs[i];
+                white_output[i] = white * gain;
+                pink_output[i] = pink * gain;
+                brown_output[i] = brown * gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+
+    /// Renders `blocks` blocks of every voice at full amplitude, handing each block's outputs to `each`
+    fn render_blocks(noise: &mut PolyNoise, blocks: usize, mut each: impl FnMut(&[Block])) {
+        noise.rng = StdRng::seed_from_u64(1);
+        noise.set_level_value(1.0);
+        let inputs = vec![[1.0; BLOCK_SIZE]; TOTAL_INPUT_COUNT];
+        let mut outputs = vec![[0.0; BLOCK_SIZE]; TOTAL_OUTPUT_COUNT];
+        for _ in 0..blocks {
+            noise.render(&inputs, &mut outputs, BLOCK_SIZE, 48000.0);
+            each(&outputs);
+        }
+    }
+
+    /// Average power of the DFT bins around `bin` over Hann windowed segments of `segment_len` samples
+    fn power_around(samples: &[f32], segment_len: usize, bin: usize) -> f64 {
+        let window: Vec<f64> = (0..segment_len).map(|n| 0.5 - 0.5 * (TAU * n as f64 / segment_len as f64).cos()).collect();
+        let twiddles: Vec<(f64, f64)> = (0..segment_len).map(|n| {
+            let phase = TAU * n as f64 / segment_len as f64;
+            (phase.cos(), phase.sin())
+        }).collect();
+
+        let mut power = 0.0;
+        let mut count = 0;
+        for segment in samples.chunks_exact(segment_len) {
+            for k in bin - 2..=bin + 2 {
+                let (mut re, mut im) = (0.0, 0.0);
+                for (n, &sample) in segment.iter().enumerate() {
+                    let (cos, sin) = twiddles[k * n % segment_len];
+                    re += window[n] * sample as f64 * cos;
+                    im -= window[n] * sample as f64 * sin;
+                }
+                power += re * re + im * im;
+                count += 1;
+            }
+        }
+        power / count as f64
+    }
+
+    #[test]
+    fn brown_noise_stays_bounded() {
+        let mut noise = PolyNoise::new();
+        let mut loudest = 0.0f32;
+        // Five seconds of every voice at 48 kHz, thousands of times the integrator's time constant
+        render_blocks(&mut noise, 3750, |outputs| {
+            for sample in outputs[BROWN_OUTPUT..BROWN_OUTPUT + MAX_POLY_COUNT].iter().flatten() {
+                loudest = loudest.max(sample.abs());
+            }
+        });
+        assert!(loudest < BROWN_GAIN, "brown noise reached {loudest}");
+    }
+
+    #[test]
+    fn pink_noise_falls_3_db_per_octave() {
+        let mut noise = PolyNoise::new();
+        let segment_len = 4096;
+        let mut pink = Vec::new();
+        render_blocks(&mut noise, 32 * segment_len / BLOCK_SIZE, |outputs| pink.extend_from_slice(&outputs[PINK_OUTPUT]));
+
+        // Octaves from 190 Hz to 3 kHz at 48 kHz, the slope is fitted across all of them to average out the noise
+        let levels: Vec<f64> = [16, 32, 64, 128, 256].iter()
+            .map(|&bin| 10.0 * power_around(&pink, segment_len, bin).log10())
+            .collect();
+        let mean = levels.iter().sum::<f64>() / levels.len() as f64;
+        let center = (levels.len() - 1) as f64 / 2.0;
+        let (covariance, variance) = levels.iter().enumerate().fold((0.0, 0.0), |(covariance, variance), (octave, level)| {
+            let offset = octave as f64 - center;
+            (covariance + offset * (level - mean), variance + offset * offset)
+        });
+        let slope = covariance / variance;
+        assert!((slope + 3.0).abs() < 0.5, "slope was {slope} dB/oct in {levels:?}");
+    }
+
+    #[test]
+    fn shared_voices_play_the_same_noise() {
+        for shared in [true, false] {
+            let mut noise = PolyNoise::new();
+            noise.set_shared(shared);
+            let mut identical = [true; 3];
+            render_blocks(&mut noise, 16, |outputs| {
+                for (identical, output) in identical.iter_mut().zip([WHITE_OUTPUT, PINK_OUTPUT, BROWN_OUTPUT]) {
+                    *identical &= (1..MAX_POLY_COUNT).all(|voice| outputs[output + voice] == outputs[output]);
+                }
+            });
+            assert_eq!(identical, [shared; 3]);
+        }
+    }
+}
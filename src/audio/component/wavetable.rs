@@ -1,39 +1,180 @@
-use crate::audio::MAX_POLY_COUNT;
+use std::fmt;
+
+use crate::audio::{module::Module, Block, MAX_POLY_COUNT};
+use super::{Unison, MAX_UNISON};
 
 pub const LEVEL_INPUT: usize = 0 * MAX_POLY_COUNT;
 pub const FREQUENCY_INPUT: usize = 1 * MAX_POLY_COUNT;
 pub const PHASE_INPUT: usize = 2 * MAX_POLY_COUNT;
 pub const AMP_INPUT: usize = 3 * MAX_POLY_COUNT;
-pub const TOTAL_INPUT_COUNT: usize = 4 * MAX_POLY_COUNT;
+/// Hard sync, takes another oscillator's `SYNC_OUTPUT`
+pub const SYNC_INPUT: usize = 4 * MAX_POLY_COUNT;
+/// Linear FM, scaled by the FM index
+pub const FM_INPUT: usize = 5 * MAX_POLY_COUNT;
+/// Added to the position knob, 1 is a sweep over the whole table
+pub const POSITION_INPUT: usize = 6 * MAX_POLY_COUNT;
+pub const TOTAL_INPUT_COUNT: usize = 7 * MAX_POLY_COUNT;
 
 pub const OUT_VALUE: usize = 0 * MAX_POLY_COUNT;
-pub const TOTAL_OUTPUT_COUNT: usize = 1 * MAX_POLY_COUNT;
+/// Samples until the phase wraps when that happens before the next sample, otherwise 0
+pub const SYNC_OUTPUT: usize = 1 * MAX_POLY_COUNT;
+/// Stereo side signal of the unison spread, half of left minus right, cables from `OUT_VALUE` carry it along
+pub const SIDE_OUTPUT: usize = 2 * MAX_POLY_COUNT;
+pub const TOTAL_OUTPUT_COUNT: usize = 3 * MAX_POLY_COUNT;
+
+pub const LEVEL_PARAMETER: usize = 0;
+pub const FREQUENCY_PARAMETER: usize = 1;
+pub const PHASE_PARAMETER: usize = 2;
+pub const FM_INDEX_PARAMETER: usize = 3;
+pub const UNISON_PARAMETER: usize = 4;
+pub const DETUNE_PARAMETER: usize = 5;
+pub const SPREAD_PARAMETER: usize = 6;
+pub const POSITION_PARAMETER: usize = 7;
 
 pub const WAVETABLE_FRAME_LENGTH: usize = 2048;
-pub const WAVETABLE_VARIATION_COUNT: usize = 8;
-pub type Wavetable = [f32; WAVETABLE_FRAME_LENGTH * WAVETABLE_VARIATION_COUNT];
+/// Each variation halves the partials of the one before, the last one is only the fundamental
+pub const WAVETABLE_VARIATION_COUNT: usize = 10;
+pub const MAX_WAVETABLE_FRAMES: usize = 256;
+
+/// Samples the windowed sinc reads around the phase, half before and half after
+const SINC_TAPS: usize = 8;
+/// Fractional positions the sinc kernel is precomputed at, others are interpolated between them
+const SINC_PHASES: usize = 512;
+
+/// How samples between the stored ones are read, the later ones cost more CPU for less roll-off and noise
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    Hermite,
+    Sinc,
+}
+
+/// Frames to scan through with the position, each one stored as `WAVETABLE_VARIATION_COUNT`
+/// band-limited variations of `WAVETABLE_FRAME_LENGTH` samples
+#[derive(Clone)]
+pub struct Wavetable {
+    frame_count: usize,
+    samples: Box<[f32]>,
+}
+
+impl Wavetable {
+    /// Silent table, `frame_count` is kept within `1..=MAX_WAVETABLE_FRAMES`
+    pub fn new(frame_count: usize) -> Self {
+        let frame_count = frame_count.clamp(1, MAX_WAVETABLE_FRAMES);
+        Self {
+            frame_count,
+            samples: vec![0.0; frame_count * WAVETABLE_VARIATION_COUNT * WAVETABLE_FRAME_LENGTH].into_boxed_slice(),
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    #[inline(always)]
+    pub fn variation(&self, frame: usize, variation: usize) -> &[f32] {
+        let start = (frame * WAVETABLE_VARIATION_COUNT + variation) * WAVETABLE_FRAME_LENGTH;
+        &self.samples[start..start + WAVETABLE_FRAME_LENGTH]
+    }
+
+    pub fn variation_mut(&mut self, frame: usize, variation: usize) -> &mut [f32] {
+        let start = (frame * WAVETABLE_VARIATION_COUNT + variation) * WAVETABLE_FRAME_LENGTH;
+        &mut self.samples[start..start + WAVETABLE_FRAME_LENGTH]
+    }
+
+    /// Every sample of every frame and variation
+    pub fn samples_mut(&mut self) -> &mut [f32] {
+        &mut self.samples
+    }
+}
 
-pub struct PolyWavetable<const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> {
+/// Blackman windowed sinc kernels, one row of `SINC_TAPS` weights per fractional position
+struct SincTable {
+    table: [[f32; SINC_TAPS]; SINC_PHASES + 1],
+}
+
+impl SincTable {
+    fn new() -> Self {
+        let half_width = (SINC_TAPS / 2) as f64;
+        let table = std::array::from_fn(|phase| {
+            let fraction = phase as f64 / SINC_PHASES as f64;
+            let mut row: [f64; SINC_TAPS] = std::array::from_fn(|tap| {
+                let x = (tap as f64 - (half_width - 1.0)) - fraction;
+                let sinc = if x == 0.0 { 1.0 } else { (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x) };
+                let window = 0.42 + 0.5 * (std::f64::consts::PI * x / half_width).cos() + 0.08 * (2.0 * std::f64::consts::PI * x / half_width).cos();
+                sinc * window
+            });
+            // Unity gain at DC, otherwise the level ripples with the phase
+            let sum: f64 = row.iter().sum();
+            row.iter_mut().for_each(|weight| *weight /= sum);
+            row.map(|weight| weight as f32)
+        });
+        Self { table }
+    }
+
+    #[inline(always)]
+    fn interp(&self, variation: &[f32], current_phase: f32) -> f32 {
+        let index = current_phase as usize;
+        let position = current_phase.fract() * SINC_PHASES as f32;
+        let row = (position as usize).min(SINC_PHASES - 1);
+        let row_ratio = position - row as f32;
+        let start = index + WAVETABLE_FRAME_LENGTH - (SINC_TAPS / 2 - 1);
+
+        let mut value = 0.0;
+        for tap in 0..SINC_TAPS {
+            let weight = self.table[row][tap] + (self.table[row + 1][tap] - self.table[row][tap]) * row_ratio;
+            value += variation[(start + tap) % WAVETABLE_FRAME_LENGTH] * weight;
+        }
+        value
+    }
+}
+
+impl fmt::Debug for Wavetable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wavetable")
+            .field("frame_count", &self.frame_count)
+            .finish()
+    }
+}
+
+pub struct PolyWavetable {
     wavetable: Box<Wavetable>,
     level: f32,
     frequency: f32,
     phase: f32,
-    current_phases: [f32; MAX_POLY_COUNT],
+    fm_index: f32,
+    through_zero: bool,
+    position: f32,
+    interpolation: Interpolation,
+    sinc_table: Box<SincTable>,
+    unison: Unison,
+    current_phases: [[f32; MAX_UNISON]; MAX_POLY_COUNT],
+    /// Second half of the band-limited step of a sync reset, added to the sample after it
+    sync_corrections: [[f32; MAX_UNISON]; MAX_POLY_COUNT],
 }
 
-impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyWavetable <INPUT_OFFSET, OUTPUT_OFFSET> {
+impl PolyWavetable {
     pub fn new() -> Self {
         Self {
-            wavetable: Box::new([0.0; WAVETABLE_FRAME_LENGTH * WAVETABLE_VARIATION_COUNT]),
+            wavetable: Box::new(Wavetable::new(1)),
             level: 0.5,
             frequency: 0.0,
             phase: 0.0,
-            current_phases: [0.0; MAX_POLY_COUNT],
+            fm_index: 0.0,
+            through_zero: false,
+            position: 0.0,
+            interpolation: Interpolation::default(),
+            sinc_table: Box::new(SincTable::new()),
+            unison: Unison::new(),
+            current_phases: [[0.0; MAX_UNISON]; MAX_POLY_COUNT],
+            sync_corrections: [[0.0; MAX_UNISON]; MAX_POLY_COUNT],
         }
     }
 
-    pub fn update_wavetable(&mut self, new_wavetable: Box<Wavetable>) {
-        self.wavetable = new_wavetable;
+    /// Returns the replaced wavetable so the caller decides where it gets freed
+    pub fn update_wavetable(&mut self, new_wavetable: Box<Wavetable>) -> Box<Wavetable> {
+        std::mem::replace(&mut self.wavetable, new_wavetable)
     }
 
     pub fn set_freq_value(&mut self, freq: f32) {
@@ -48,37 +189,217 @@ impl <const INPUT_OFFSET: usize, const OUTPUT_OFFSET: usize> PolyWavetable <INPU
         self.level = level;
     }
 
+    pub fn set_fm_index_value(&mut self, fm_index: f32) {
+        self.fm_index = fm_index * super::MAX_FM_INDEX;
+    }
+
+    pub fn set_through_zero(&mut self, through_zero: bool) {
+        self.through_zero = through_zero;
+    }
+
+    pub fn set_position_value(&mut self, position: f32) {
+        self.position = position;
+    }
+
+    pub fn set_interpolation(&mut self, interpolation: Interpolation) {
+        self.interpolation = interpolation;
+    }
+
+    pub fn set_unison_value(&mut self, unison: f32) {
+        for copy in self.unison.set_count_value(unison) {
+            for (current_phases, sync_corrections) in self.current_phases.iter_mut().zip(self.sync_corrections.iter_mut()) {
+                current_phases[copy] = self.unison.random_phase() as f32 * WAVETABLE_FRAME_LENGTH as f32;
+                sync_corrections[copy] = 0.0;
+            }
+        }
+    }
+
+    pub fn set_detune_value(&mut self, detune: f32) {
+        self.unison.set_detune_value(detune);
+    }
+
+    pub fn set_spread_value(&mut self, spread: f32) {
+        self.unison.set_spread_value(spread);
+    }
+}
+
+impl Module for PolyWavetable {
+    fn input_count(&self) -> usize {
+        TOTAL_INPUT_COUNT
+    }
+
+    fn output_count(&self) -> usize {
+        TOTAL_OUTPUT_COUNT
+    }
+
+    fn side_output(&self, jack: usize) -> Option<usize> {
+        (jack == OUT_VALUE).then_some(SIDE_OUTPUT)
+    }
+
+    fn set_parameter(&mut self, parameter: usize, value: f32) {
+        match parameter {
+            LEVEL_PARAMETER => self.set_level_value(value),
+            FREQUENCY_PARAMETER => self.set_freq_value(value),
+            PHASE_PARAMETER => self.set_phase_value(value),
+            FM_INDEX_PARAMETER => self.set_fm_index_value(value),
+            UNISON_PARAMETER => self.set_unison_value(value),
+            DETUNE_PARAMETER => self.set_detune_value(value),
+            SPREAD_PARAMETER => self.set_spread_value(value),
+            POSITION_PARAMETER => self.set_position_value(value),
+            _ => {},
+        }
+    }
+
+    fn render(&mut self, inputs: &[Block], outputs: &mut [Block], len: usize, sample_rate: f64) {
+        let sample_rate = sample_rate as f32;
+        let reader = Reader {
+            wavetable: &self.wavetable,
+            interpolation: self.interpolation,
+            sinc_table: &self.sinc_table,
+        };
+        let voices = self.current_phases.iter_mut().zip(self.sync_corrections.iter_mut());
+        for (wavetable, (current_phases, sync_corrections)) in voices.enumerate() {
+            let phase_inputs = &inputs[PHASE_INPUT + wavetable];
+            let frequency_inputs = &inputs[FREQUENCY_INPUT + wavetable];
+            let level_inputs = &inputs[LEVEL_INPUT + wavetable];
+            let amp_inputs = &inputs[AMP_INPUT + wavetable];
+            let sync_inputs = &inputs[SYNC_INPUT + wavetable];
+            let fm_inputs = &inputs[FM_INPUT + wavetable];
+            let position_inputs = &inputs[POSITION_INPUT + wavetable];
+            let [output, sync_output, side_output] = outputs
+                .get_disjoint_mut([OUT_VALUE + wavetable, SYNC_OUTPUT + wavetable, SIDE_OUTPUT + wavetable])
+                .unwrap();
+
+            for i in 0..len {
+                let phase_input = phase_inputs[i] * WAVETABLE_FRAME_LENGTH as f32;
+                let level = self.level + level_inputs[i];
+                let voltage = self.frequency + frequency_inputs[i];
+                let frequency =  super::calculate_freq(voltage);// C-1 (midi note 0)
+                let frequency = super::linear_fm(frequency, fm_inputs[i], self.fm_index, self.through_zero);
+                let sync = sync_inputs[i];
+                let last_frame = (self.wavetable.frame_count() - 1) as f32;
+                let position = (self.position + position_inputs[i]).clamp(0.0, 1.0) * last_frame;
+
+                let mut mid = 0.0;
+                let mut side = 0.0;
+                for copy in 0..self.unison.count() {
+                    let current_phase = &mut current_phases[copy];
+                    let sync_correction = &mut sync_corrections[copy];
+                    let phase = (*current_phase + phase_input) % WAVETABLE_FRAME_LENGTH as f32;
+
+                    let phase_increment = frequency * self.unison.ratio(copy) as f32 / sample_rate * WAVETABLE_FRAME_LENGTH as f32;
+                    let mipmap = mipmap_level(phase_increment);
+                    let mut raw = reader.read_position(position, mipmap, phase) + std::mem::take(sync_correction);
+
+                    let next_phase = *current_phase + phase_increment;
+                    if copy == 0 {
+                        sync_output[i] = if next_phase >= WAVETABLE_FRAME_LENGTH as f32 {
+                            (WAVETABLE_FRAME_LENGTH as f32 - *current_phase) / phase_increment
+                        } else if next_phase < 0.0 {
+                            *current_phase / -phase_increment
+                        } else {
+                            0.0
+                        };
+                    }
+
+                    if sync > 0.0 {
+                        // The reset lands `sync` samples from now, spread the step over this sample and the next one
+                        let before_phase = (phase + sync * phase_increment).rem_euclid(WAVETABLE_FRAME_LENGTH as f32);
+                        let before = reader.read_position(position, mipmap, before_phase);
+                        let after = reader.read_position(position, mipmap, phase_input.rem_euclid(WAVETABLE_FRAME_LENGTH as f32));
+                        let half_step = (after - before) / 2.0;
+                        raw += half_step * (1.0 - sync) * (1.0 - sync);
+                        *sync_correction = -half_step * sync * sync;
+                        *current_phase = ((1.0 - sync) * phase_increment).rem_euclid(WAVETABLE_FRAME_LENGTH as f32);
+                    } else {
+                        *current_phase = next_phase.rem_euclid(WAVETABLE_FRAME_LENGTH as f32);
+                    }
+
+                    mid += raw;
+                    side -= raw * self.unison.pan(copy);
+                }
+
+                let gain = self.unison.gain() * level * amp_inputs[i];
+                output[i] = mid * gain;
+                side_output[i] = side * gain;
+            }
+        }
+    }
+}
+
+/// Variation to play at a phase increment in samples, the fraction fades into the next one.
+/// Variation `v` keeps the partials below `WAVETABLE_FRAME_LENGTH >> (v + 1)`, so starting one
+/// level above the log of the increment keeps the top partial of both faded variations under Nyquist
+#[inline(always)]
+fn mipmap_level(phase_increment: f32) -> f32 {
+    (phase_increment.abs().log2() + 1.0).clamp(0.0, (WAVETABLE_VARIATION_COUNT - 1) as f32)
+}
+
+/// Everything a sample lookup needs, borrowed apart from the per voice state
+struct Reader<'a> {
+    wavetable: &'a Wavetable,
+    interpolation: Interpolation,
+    sinc_table: &'a SincTable,
+}
+
+impl Reader<'_> {
+    /// Crossfades the frames either side of `position`, which runs from 0 to the last frame
     #[inline(always)]
-    pub fn render(&mut self, inputs: &[f32], outputs: &mut [f32], sample_rate: f32) {
-        for (wavetable, current_phase) in self.current_phases.iter_mut().enumerate() {
-            let phase_input = inputs[INPUT_OFFSET + PHASE_INPUT + wavetable] * WAVETABLE_FRAME_LENGTH as f32;
-            let frequency_input = inputs[INPUT_OFFSET + FREQUENCY_INPUT + wavetable];
-            let level_input = inputs[INPUT_OFFSET + LEVEL_INPUT + wavetable];
-            let amp_input = inputs[INPUT_OFFSET + AMP_INPUT + wavetable];
+    fn read_position(&self, position: f32, mipmap: f32, current_phase: f32) -> f32 {
+        let frame = position as usize;
+        let frame_ratio = position.fract();
 
-            let level = self.level + level_input;
-            let voltage = self.frequency + frequency_input;
-            let frequency =  super::calculate_freq(voltage);// C-1 (midi note 0)
-            let phase = (*current_phase + phase_input) % WAVETABLE_FRAME_LENGTH as f32;
+        let value = self.read_frame(frame, mipmap, current_phase);
+        if frame_ratio == 0.0 {
+            return value;
+        }
+        let next_value = self.read_frame(frame + 1, mipmap, current_phase);
+        value + (next_value - value) * frame_ratio
+    }
 
-            let phase_increment = frequency / sample_rate * WAVETABLE_FRAME_LENGTH as f32;
-            let raw = linear_interp(&self.wavetable, phase, voltage);
+    /// Crossfades the variations either side of `mipmap`
+    #[inline(always)]
+    fn read_frame(&self, frame: usize, mipmap: f32, current_phase: f32) -> f32 {
+        let variation = mipmap as usize;
+        let variation_ratio = mipmap.fract();
 
-            *current_phase = (*current_phase + phase_increment) % WAVETABLE_FRAME_LENGTH as f32;
+        let value = self.interp(self.wavetable.variation(frame, variation), current_phase);
+        if variation_ratio == 0.0 {
+            return value;
+        }
+        let next_value = self.interp(self.wavetable.variation(frame, variation + 1), current_phase);
+        value + (next_value - value) * variation_ratio
+    }
 
-            let scaled_raw = raw as f32 * level * amp_input;
-            outputs[OUTPUT_OFFSET + OUT_VALUE + wavetable] = scaled_raw;
+    #[inline(always)]
+    fn interp(&self, variation: &[f32], current_phase: f32) -> f32 {
+        match self.interpolation {
+            Interpolation::Linear => linear_interp(variation, current_phase),
+            Interpolation::Hermite => hermite_interp(variation, current_phase),
+            Interpolation::Sinc => self.sinc_table.interp(variation, current_phase),
         }
     }
 }
 
-fn linear_interp(wavetable: &Wavetable, current_phase: f32, frequency_voltage: f32) -> f32 {
-    let variation = ((frequency_voltage * 128.0 - 30.0) / 10.0).clamp(0.0, WAVETABLE_VARIATION_COUNT as f32 - 0.1) as usize;
-    // FIX THIS TO BE BETTER ////// MAYBE IT NEEDS TO FADE?
-    let variation_offset = variation * WAVETABLE_FRAME_LENGTH;
-    let index1 = variation_offset + current_phase as usize;
-    let index2 = variation_offset + (index1 + 1) % WAVETABLE_FRAME_LENGTH;
+fn linear_interp(variation: &[f32], current_phase: f32) -> f32 {
+    let index1 = current_phase as usize % WAVETABLE_FRAME_LENGTH;
+    let index2 = (index1 + 1) % WAVETABLE_FRAME_LENGTH;
     let index_ratio = current_phase.fract();
 
-    wavetable[index1] + (wavetable[index2] - wavetable[index1]) * index_ratio
-}
\ No newline at end of file
+    variation[index1] + (variation[index2] - variation[index1]) * index_ratio
+}
+
+/// Catmull-Rom spline through the two samples either side of the phase
+fn hermite_interp(variation: &[f32], current_phase: f32) -> f32 {
+    let index = current_phase as usize % WAVETABLE_FRAME_LENGTH;
+    let before = variation[(index + WAVETABLE_FRAME_LENGTH - 1) % WAVETABLE_FRAME_LENGTH];
+    let value1 = variation[index];
+    let value2 = variation[(index + 1) % WAVETABLE_FRAME_LENGTH];
+    let after = variation[(index + 2) % WAVETABLE_FRAME_LENGTH];
+    let t = current_phase.fract();
+
+    let slope = 0.5 * (value2 - before);
+    let curve = before - 2.5 * value1 + 2.0 * value2 - 0.5 * after;
+    let cubic = 0.5 * (after - before) + 1.5 * (value1 - value2);
+    ((cubic * t + curve) * t + slope) * t + value1
+}
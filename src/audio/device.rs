@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{BufferSize, Device, Host, SampleRate, StreamConfig, SupportedBufferSize, SupportedStreamConfig};
+
+const SETTINGS_FILE: &str = "audio_settings.cfg";
+const APP_DIR: &str = "cav-synth2";
+/// Passing this as a value forgets the remembered choice
+const DEFAULT_VALUE: &str = "default";
+
+const USAGE: &str = "Usage: cav-synth2 [--host <name>] [--device <name>] [--sample-rate <hz>] [--buffer-size <frames>] [--list-devices]";
+
+/// Output device choice, unset fields fall back to the system defaults
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct AudioSettings {
+    pub host: Option<String>,
+    pub device: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub buffer_size: Option<u32>,
+}
+
+impl AudioSettings {
+    /// Reads the settings remembered from the last run, a missing or malformed file gives the defaults
+    pub fn load() -> Self {
+        let mut settings = Self::default();
+        let Some(path) = settings_path() else {
+            return settings;
+        };
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return settings;
+        };
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "host" => settings.host = Some(value.to_string()),
+                "device" => settings.device = Some(value.to_string()),
+                "sample_rate" => settings.sample_rate = value.parse().ok(),
+                "buffer_size" => settings.buffer_size = value.parse().ok(),
+                _ => {},
+            }
+        }
+        settings
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let mut contents = String::new();
+        if let Some(host) = &self.host {
+            contents += &format!("host={host}\n");
+        }
+        if let Some(device) = &self.device {
+            contents += &format!("device={device}\n");
+        }
+        if let Some(sample_rate) = self.sample_rate {
+            contents += &format!("sample_rate={sample_rate}\n");
+        }
+        if let Some(buffer_size) = self.buffer_size {
+            contents += &format!("buffer_size={buffer_size}\n");
+        }
+        let path = settings_path().ok_or("Failed to save audio settings: No config directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| format!("Failed to create '{}': {err}", dir.display()))?;
+        }
+        std::fs::write(&path, contents)
+            .map_err(|err| format!("Failed to save '{}': {err}", path.display()))
+    }
+}
+
+/// Settings file in the per-user config directory
+fn settings_path() -> Option<PathBuf> {
+    let env_dir = |name| std::env::var_os(name).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+    let config_dir = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+    };
+    config_dir.map(|dir| dir.join(APP_DIR).join(SETTINGS_FILE))
+}
+
+/// Applies command line options on top of the remembered settings and remembers the result
+///
+/// Returns `None` when the arguments only asked for the device list.
+pub fn run_cli(args: &[String]) -> Result<Option<AudioSettings>, String> {
+    let remembered = AudioSettings::load();
+    let mut settings = remembered.clone();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" => settings.host = parse_flag_value(arg, args.next())?,
+            "--device" => settings.device = parse_flag_value(arg, args.next())?,
+            "--sample-rate" => settings.sample_rate = parse_flag_value(arg, args.next())?,
+            "--buffer-size" => settings.buffer_size = parse_flag_value(arg, args.next())?,
+            "--list-devices" => {
+                list_devices();
+                return Ok(None);
+            },
+            _ => return Err(format!("Unknown option '{arg}'\n{USAGE}")),
+        }
+    }
+    if settings != remembered && let Err(err) = settings.save() {
+        eprintln!("{err}");
+    }
+    Ok(Some(settings))
+}
+
+fn parse_flag_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<Option<T>, String> {
+    match value {
+        Some(value) if value == DEFAULT_VALUE => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(|_| format!("Invalid value '{value}' for '{flag}'")),
+        None => Err(format!("Missing value for '{flag}'")),
+    }
+}
+
+/// Prints every available host, its output devices and their supported configs
+pub fn list_devices() {
+    let default_host = cpal::default_host().id();
+    for host_id in cpal::available_hosts() {
+        let default_marker = if host_id == default_host { " (default)" } else { "" };
+        println!("Host: {}{default_marker}", host_id.name());
+        let Ok(host) = cpal::host_from_id(host_id) else {
+            println!("  Unavailable");
+            continue;
+        };
+        let default_device = host.default_output_device().and_then(|device| device.name().ok());
+        let Ok(devices) = host.output_devices() else {
+            println!("  Failed to enumerate output devices");
+            continue;
+        };
+        for device in devices {
+            let name = device.name().unwrap_or_else(|_| String::from("<unknown>"));
+            let default_marker = if default_device.as_ref() == Some(&name) { " (default)" } else { "" };
+            println!("  Device: {name}{default_marker}");
+            let Ok(configs) = device.supported_output_configs() else {
+                continue;
+            };
+            for config in configs {
+                let buffer_size = match config.buffer_size() {
+                    SupportedBufferSize::Range { min, max } => format!("{min}-{max} frames"),
+                    SupportedBufferSize::Unknown => String::from("unknown"),
+                };
+                println!(
+                    "    {} channels, {}-{} Hz, {}, buffer {buffer_size}",
+                    config.channels(), config.min_sample_rate().0, config.max_sample_rate().0, config.sample_format(),
+                );
+            }
+        }
+    }
+}
+
+/// Resolves the settings to a device and config, choices that are no longer available fall back to the defaults
+pub fn host_device_setup(settings: &AudioSettings) -> Result<(Host, Device, SupportedStreamConfig, StreamConfig), String> {
+    let host = select_host(settings.host.as_deref());
+    let device = select_device(&host, settings.device.as_deref())?;
+    let config = select_config(&device, settings.sample_rate)?;
+
+    let mut stream_config: StreamConfig = config.clone().into();
+    if let Some(frames) = settings.buffer_size {
+        match config.buffer_size() {
+            SupportedBufferSize::Range { min, max } if !(*min..=*max).contains(&frames) => {
+                eprintln!("Buffer size {frames} is outside the supported {min}-{max} frames, using the default");
+            },
+            _ => stream_config.buffer_size = BufferSize::Fixed(frames),
+        }
+    }
+    Ok((host, device, config, stream_config))
+}
+
+fn select_host(name: Option<&str>) -> Host {
+    let Some(name) = name else {
+        return cpal::default_host();
+    };
+    let host = cpal::available_hosts()
+        .into_iter()
+        .find(|host_id| host_id.name().eq_ignore_ascii_case(name))
+        .and_then(|host_id| cpal::host_from_id(host_id).ok());
+    host.unwrap_or_else(|| {
+        eprintln!("Audio host '{name}' is not available, using the default");
+        cpal::default_host()
+    })
+}
+
+fn select_device(host: &Host, name: Option<&str>) -> Result<Device, String> {
+    let named_device = name.and_then(|name| {
+        let device = host.output_devices()
+            .ok()?
+            .find(|device| device.name().is_ok_and(|device_name| device_name == name));
+        if device.is_none() {
+            eprintln!("Output device '{name}' is not available, using the default");
+        }
+        device
+    });
+    named_device
+        .or_else(|| host.default_output_device())
+        .ok_or_else(|| String::from("Default output device is not available"))
+}
+
+fn select_config(device: &Device, sample_rate: Option<u32>) -> Result<SupportedStreamConfig, String> {
+    let default_config = device
+        .default_output_config()
+        .map_err(|err| format!("Default output config is unavailable: {}", err))?;
+    let Some(sample_rate) = sample_rate.filter(|&rate| rate != default_config.sample_rate().0) else {
+        return Ok(default_config);
+    };
+    let configs = device
+        .supported_output_configs()
+        .map_err(|err| format!("Failed to query output configs: {}", err))?;
+    // Stay as close to the default layout as possible, only the rate should change
+    let config = configs
+        .filter_map(|config| config.try_with_sample_rate(SampleRate(sample_rate)))
+        .max_by_key(|config| (
+            config.channels() == default_config.channels(),
+            config.sample_format() == default_config.sample_format(),
+        ));
+    Ok(config.unwrap_or_else(|| {
+        eprintln!("Sample rate {sample_rate} Hz is not supported by the device, using {} Hz", default_config.sample_rate().0);
+        default_config
+    }))
+}
@@ -1,6 +1,6 @@
 use std::collections::VecDeque;
 
-use crate::audio::{MAX_POLY_COUNT, MIDI_OUTPUT_OFFSET};
+use crate::audio::{module::Module, Block, MAX_POLY_COUNT};
 
 pub const DC_OUTPUT: usize = 0;
 pub const GATE_OUTPUT: usize = 0 * MAX_POLY_COUNT + 1;
@@ -14,6 +14,9 @@ struct Voice {
     trigger: bool,
     ready: bool,
     on: bool,
+    note: f32,
+    gate: f32,
+    velocity: f32,
 }
 
 impl Voice {
@@ -23,17 +26,33 @@ impl Voice {
             trigger: false,
             ready: false,
             on: false,
+            note: 0.0,
+            gate: 0.0,
+            velocity: 0.0,
         }
     }
 
-    fn update(&mut self, output: &mut [f32], voice_index: usize, note: u8, velocity: u8) {
+    fn update(&mut self, note: u8, velocity: u8) {
         self.pressed = true;
         self.trigger = true;
         self.ready = false;
         self.on = true;
-        output[MIDI_OUTPUT_OFFSET + NOTE_OUTPUT + voice_index] = note as f32 / 128.0;
-        output[MIDI_OUTPUT_OFFSET + GATE_OUTPUT + voice_index] = 0.0;
-        output[MIDI_OUTPUT_OFFSET + VELOCITY_OUTPUT + voice_index] = velocity as f32 / 128.0;
+        self.note = note as f32 / 128.0;
+        self.gate = 0.0;
+        self.velocity = velocity as f32 / 128.0;
+    }
+
+    /// Moves the retrigger on by one sample, the gate drops for the first sample after a key press
+    #[inline(always)]
+    fn tick(&mut self) -> f32 {
+        if self.trigger {
+            self.ready = true;
+            self.trigger = false;
+        } else if self.ready {
+            self.gate = 1.0;
+            self.ready = false;
+        }
+        self.gate
     }
 }
 
@@ -48,20 +67,31 @@ pub struct Midi {
     replace_queue: VecDeque<usize>,
 }
 
-impl Midi {
-    pub fn process(&mut self, output: &mut [f32]) {
+impl Module for Midi {
+    fn input_count(&self) -> usize {
+        0
+    }
+
+    fn output_count(&self) -> usize {
+        TOTAL_OUTPUT_COUNT
+    }
+
+    fn set_parameter(&mut self, _parameter: usize, _value: f32) {}
+
+    fn render(&mut self, _inputs: &[Block], output: &mut [Block], len: usize, _sample_rate: f64) {
+        output[DC_OUTPUT][..len].fill(1.0);
         for (i, voice) in self.voices.iter_mut().enumerate() {
-            if voice.trigger {
-                voice.ready = true;
-                voice.trigger = false;
-            } else if voice.ready {
-                output[MIDI_OUTPUT_OFFSET + GATE_OUTPUT + i] = 1.0;
-                voice.ready = false;
+            for gate in output[GATE_OUTPUT + i][..len].iter_mut() {
+                *gate = voice.tick();
             }
+            output[NOTE_OUTPUT + i][..len].fill(voice.note);
+            output[VELOCITY_OUTPUT + i][..len].fill(voice.velocity);
         }
     }
+}
 
-    pub fn key_press(&mut self, output: &mut [f32], note: u8, velocity: u8) {
+impl Midi {
+    pub fn key_press(&mut self, note: u8, velocity: u8) {
         // Poly
         let new_voice;
         if let Some(voice) = self.voices.iter().cycle().skip(self.next).take(MAX_POLY_COUNT).position(|voice| !voice.on ) {
@@ -70,19 +100,19 @@ impl Midi {
         } else {
             new_voice = self.replace_queue.pop_front().unwrap();
         }
-        self.voices[new_voice].update(output, new_voice, velocity, note);
+        self.voices[new_voice].update(velocity, note);
         self.replace_queue.push_back(new_voice);
     }
 
-    pub fn key_release(&mut self, output: &mut [f32], note: u8) {
+    pub fn key_release(&mut self, note: u8) {
         let note_signal = note as f32 / 128.0;
         // Poly
         for (i, voice) in self.voices.iter_mut().enumerate() {
-            if output[MIDI_OUTPUT_OFFSET + NOTE_OUTPUT + i] == note_signal {
+            if voice.note == note_signal {
                 if !self.sustain && voice.on {
                     voice.ready = false;
                     voice.trigger = false;
-                    output[MIDI_OUTPUT_OFFSET + GATE_OUTPUT + i] = 0.0;
+                    voice.gate = 0.0;
                     voice.on = false;
                     
                     let queue_pos = self.replace_queue.iter().position(|voice_num| *voice_num == i).unwrap();
@@ -97,7 +127,7 @@ impl Midi {
         self.sustain = true;
     }
 
-    pub fn pedal_release(&mut self, output: &mut [f32]) {
+    pub fn pedal_release(&mut self) {
         self.sustain = false; 
 
         // Poly
@@ -105,7 +135,7 @@ impl Midi {
             if voice.on && !voice.pressed {
                 voice.ready = false;
                 voice.trigger = false;
-                output[MIDI_OUTPUT_OFFSET + GATE_OUTPUT + i] = 0.0;
+                voice.gate = 0.0;
                 voice.on = false;
                 
                 let queue_pos = self.replace_queue.iter().position(|voice_num| *voice_num == i).unwrap();
@@ -125,4 +155,26 @@ impl Midi {
             next: 0,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::BLOCK_SIZE;
+
+    fn render_gate(midi: &mut Midi, len: usize) -> Vec<f32> {
+        let mut output = vec![[0.0; BLOCK_SIZE]; TOTAL_OUTPUT_COUNT];
+        midi.render(&[], &mut output, len, 48000.0);
+        output[GATE_OUTPUT][..len].to_vec()
+    }
+
+    #[test]
+    fn retrigger_is_one_sample_at_any_block_size() {
+        for len in [1, 7, BLOCK_SIZE] {
+            let mut midi = Midi::new();
+            midi.key_press(60, 100);
+            let gate: Vec<f32> = (0..3).flat_map(|_| render_gate(&mut midi, len)).take(3).collect();
+            assert_eq!(gate, [0.0, 1.0, 1.0], "block size {len}");
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::any::Any;
+use std::fmt;
+
+use crate::audio::component::analog::PolyAnalog;
+use crate::audio::component::cable::Cables;
+use crate::audio::component::envelope::PolyEnvelope;
+use crate::audio::component::filter::PolyFilter;
+use crate::audio::component::ladder::PolyLadder;
+use crate::audio::component::lfo::PolyLfo;
+use crate::audio::component::noise::PolyNoise;
+use crate::audio::component::wavetable::PolyWavetable;
+use crate::audio::{Block, BLOCK_SIZE, MAX_MODULES};
+
+/// Index of a module in the `ModuleRegistry`
+pub type ModuleId = usize;
+
+/// A jack of a module, `jack` is the block of its first voice like the `*_INPUT`/`*_OUTPUT` constants
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PortId {
+    pub module: ModuleId,
+    pub jack: usize,
+}
+
+impl PortId {
+    pub const fn new(module: ModuleId, jack: usize) -> Self {
+        Self { module, jack }
+    }
+}
+
+pub trait Module: Any + Send {
+    /// Number of input blocks, every jack takes one block per voice
+    fn input_count(&self) -> usize;
+    /// Number of output blocks, every jack takes one block per voice
+    fn output_count(&self) -> usize;
+    /// Knob values indexed by the module's `*_PARAMETER` constants, unknown indices are ignored
+    fn set_parameter(&mut self, parameter: usize, value: f32);
+    fn render(&mut self, inputs: &[Block], outputs: &mut [Block], len: usize, sample_rate: f64);
+    /// Stereo side output that goes with the `jack` output, cables from `jack` carry it along
+    fn side_output(&self, _jack: usize) -> Option<usize> {
+        None
+    }
+    /// Stereo side input that goes with the `jack` input, cables into `jack` without one drop the side
+    fn side_input(&self, _jack: usize) -> Option<usize> {
+        None
+    }
+}
+
+/// A module together with its jack buffers, built off the audio thread so inserting it never allocates
+pub struct ModuleSlot {
+    module: Box<dyn Module>,
+    inputs: Vec<Block>,
+    outputs: Vec<Block>,
+}
+
+impl ModuleSlot {
+    pub fn new(module: Box<dyn Module>) -> Self {
+        let inputs = vec![[0.0; BLOCK_SIZE]; module.input_count()];
+        let outputs = vec![[0.0; BLOCK_SIZE]; module.output_count()];
+        Self { module, inputs, outputs }
+    }
+}
+
+impl fmt::Debug for ModuleSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModuleSlot")
+            .field("inputs", &self.inputs.len())
+            .field("outputs", &self.outputs.len())
+            .finish()
+    }
+}
+
+/// Modules that can be created at runtime
+#[derive(Clone, Copy, Debug)]
+pub enum ModuleKind {
+    Analog,
+    Wavetable,
+    Envelope,
+    Filter,
+    Ladder,
+    Lfo,
+    Noise,
+}
+
+impl ModuleKind {
+    pub fn build(self) -> ModuleSlot {
+        let module: Box<dyn Module> = match self {
+            Self::Analog => Box::new(PolyAnalog::new()),
+            Self::Wavetable => Box::new(PolyWavetable::new()),
+            Self::Envelope => Box::new(PolyEnvelope::new()),
+            Self::Filter => Box::new(PolyFilter::new()),
+            Self::Ladder => Box::new(PolyLadder::new()),
+            Self::Lfo => Box::new(PolyLfo::new()),
+            Self::Noise => Box::new(PolyNoise::new()),
+        };
+        ModuleSlot::new(module)
+    }
+}
+
+/// Owns every module and its jack buffers, modules render in the order they were added
+///
+/// Replacing a module keeps its place in the order.
+pub struct ModuleRegistry {
+    modules: Vec<Option<Box<dyn Module>>>,
+    inputs: Vec<Vec<Block>>,
+    outputs: Vec<Vec<Block>>,
+    order: Vec<ModuleId>,
+}
+
+impl ModuleRegistry {
+    pub fn new() -> Self {
+        Self {
+            modules: (0..MAX_MODULES).map(|_| None).collect(),
+            inputs: vec![Vec::new(); MAX_MODULES],
+            outputs: vec![Vec::new(); MAX_MODULES],
+            order: Vec::with_capacity(MAX_MODULES),
+        }
+    }
+
+    /// Returns the slot that has to be freed, either the replaced module or the new one if `id` is out of range
+    pub fn insert(&mut self, id: ModuleId, slot: ModuleSlot) -> Option<ModuleSlot> {
+        if id >= MAX_MODULES {
+            return Some(slot);
+        }
+        let replaced = self.take_slot(id);
+        if replaced.is_none() {
+            self.order.push(id);
+        }
+        self.modules[id] = Some(slot.module);
+        self.inputs[id] = slot.inputs;
+        self.outputs[id] = slot.outputs;
+        replaced
+    }
+
+    pub fn remove(&mut self, id: ModuleId) -> Option<ModuleSlot> {
+        let removed = self.take_slot(id)?;
+        self.order.retain(|&module_id| module_id != id);
+        Some(removed)
+    }
+
+    fn take_slot(&mut self, id: ModuleId) -> Option<ModuleSlot> {
+        let module = self.modules.get_mut(id)?.take()?;
+        Some(ModuleSlot {
+            module,
+            inputs: std::mem::take(&mut self.inputs[id]),
+            outputs: std::mem::take(&mut self.outputs[id]),
+        })
+    }
+
+    pub fn get(&self, id: ModuleId) -> Option<&dyn Module> {
+        self.modules.get(id)?.as_deref()
+    }
+
+    pub fn get_mut<T: Module>(&mut self, id: ModuleId) -> Option<&mut T> {
+        let module: &mut dyn Any = self.modules.get_mut(id)?.as_deref_mut()?;
+        module.downcast_mut()
+    }
+
+    pub fn set_parameter(&mut self, id: ModuleId, parameter: usize, value: f32) {
+        if let Some(Some(module)) = self.modules.get_mut(id) {
+            module.set_parameter(parameter, value);
+        }
+    }
+
+    /// Position of the module in the render order
+    pub fn render_position(&self, id: ModuleId) -> Option<usize> {
+        self.order.iter().position(|&module_id| module_id == id)
+    }
+
+    pub fn outputs(&self) -> &[Vec<Block>] {
+        &self.outputs
+    }
+
+    /// Feeds every module its cables and normalled connections right before rendering it
+    pub fn render<const MAX_CABLES: usize, const MAX_NORMALS: usize>(
+        &mut self,
+        cables: &Cables<MAX_CABLES>,
+        normals: &Cables<MAX_NORMALS>,
+        len: usize,
+        sample_rate: f64,
+    ) {
+        for &id in &self.order {
+            cables.run_cables(&mut self.inputs[id], &self.outputs, id, len);
+            normals.sum_cables(&mut self.inputs[id], &self.outputs, id, len);
+            if let Some(module) = &mut self.modules[id] {
+                module.render(&self.inputs[id], &mut self.outputs[id], len, sample_rate);
+            }
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use std::path::Path;
+
+use crate::audio::module::PortId;
+use crate::audio::{AudioMessage, AudioState, FilterMode, InputJack, Interpolation, ModuleKind, OutputJack, WaveShape};
+use crate::common::ring_buffer;
+use crate::wav::{self, SampleFormat};
+
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+const DEFAULT_BIT_DEPTH: u16 = 24;
+const DEFAULT_TAIL: f64 = 2.0;
+
+const USAGE: &str = "Usage: cav-synth2 render <score> <output.wav> [--sample-rate <hz>] [--bit-depth <16|24|32>] [--length <seconds>]";
+
+pub struct RenderSettings {
+    pub sample_rate: u32,
+    pub format: SampleFormat,
+    /// Total length in seconds, defaults to the last event plus a release tail
+    pub length: Option<f64>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            format: SampleFormat::from_bit_depth(DEFAULT_BIT_DEPTH).unwrap(),
+            length: None,
+        }
+    }
+}
+
+/// An `AudioMessage` scheduled at a time in seconds from the start of the render
+pub struct TimedMessage {
+    pub time: f64,
+    pub message: AudioMessage,
+}
+
+/// Entry point for `cav-synth2 render ...`
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut settings = RenderSettings::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sample-rate" => settings.sample_rate = parse_flag_value(arg, args.next())?,
+            "--bit-depth" => settings.format = SampleFormat::from_bit_depth(parse_flag_value(arg, args.next())?)?,
+            "--length" => settings.length = Some(parse_flag_value(arg, args.next())?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{arg}'\n{USAGE}")),
+            _ => positional.push(arg),
+        }
+    }
+    let [score_path, output_path] = positional[..] else {
+        return Err(String::from(USAGE));
+    };
+
+    let score = std::fs::read_to_string(score_path).map_err(|err| format!("Failed to read '{score_path}': {err}"))?;
+    let events = parse_score(&score)?;
+    render_to_file(events, &settings, Path::new(output_path))
+}
+
+fn parse_flag_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("Missing or invalid value for '{flag}'"))
+}
+
+pub fn render_to_file(events: Vec<TimedMessage>, settings: &RenderSettings, path: &Path) -> Result<(), String> {
+    let samples = render(events, settings);
+    wav::write(path, settings.sample_rate, 2, settings.format, &samples)
+}
+
+/// Renders the events without an audio device, returning interleaved stereo samples
+pub fn render(mut events: Vec<TimedMessage>, settings: &RenderSettings) -> Vec<f32> {
+    let sample_rate = settings.sample_rate as f64;
+    // Messages are handed over directly and nothing reads the queue back to the gui
+    let (sender, _) = ring_buffer(1);
+    let mut audio_state = AudioState::new(Vec::new(), sender, sample_rate);
+
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let length = settings.length.unwrap_or_else(|| {
+        events.last().map_or(0.0, |event| event.time) + DEFAULT_TAIL
+    });
+    let total_samples = (length * sample_rate) as usize;
+
+    let mut left = vec![0.0; total_samples];
+    let mut right = vec![0.0; total_samples];
+    let mut events = events.into_iter().peekable();
+    let mut position = 0;
+    while position < total_samples {
+        while let Some(event) = events.next_if(|event| (event.time * sample_rate) as usize <= position) {
+            audio_state.handle_message(event.message);
+        }
+        // Split blocks at the next event so messages land on their exact sample
+        let end = events.peek()
+            .map_or(total_samples, |event| (event.time * sample_rate) as usize)
+            .min(total_samples);
+        audio_state.process_block(&mut left[position..end], &mut right[position..end]);
+        position = end;
+    }
+
+    left.into_iter()
+        .zip(right)
+        .flat_map(|(left, right)| [left.clamp(-1.0, 1.0), right.clamp(-1.0, 1.0)])
+        .collect()
+}
+
+/// Parses a score with one event per line: `<seconds> <message> [args...]`
+///
+/// Message names match the `AudioMessage` variants, e.g. `0.5 KeyPress 60 100`.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_score(score: &str) -> Result<Vec<TimedMessage>, String> {
+    let mut events = Vec::new();
+    for (line_number, line) in score.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let parsed = tokens.next()
+            .ok_or_else(|| String::from("Missing time"))
+            .and_then(|time| time.parse::<f64>().map_err(|err| format!("Invalid time '{time}': {err}")))
+            .and_then(|time| {
+                let name = tokens.next().ok_or_else(|| String::from("Missing message"))?;
+                let args: Vec<&str> = tokens.collect();
+                let message = parse_message(name, &args)?;
+                Ok(TimedMessage { time, message })
+            });
+        match parsed {
+            Ok(event) => events.push(event),
+            Err(err) => return Err(format!("Score line {}: {err}", line_number + 1)),
+        }
+    }
+    Ok(events)
+}
+
+fn parse_message(name: &str, args: &[&str]) -> Result<AudioMessage, String> {
+    let message = match name {
+        // Osc1
+        "Osc1Freq" => AudioMessage::Osc1Freq(arg(args, 0)?),
+        "Osc1Shape" => AudioMessage::Osc1Shape(parse_shape(args)?),
+        "Osc1Phase" => AudioMessage::Osc1Phase(arg(args, 0)?),
+        "Osc1Level" => AudioMessage::Osc1Level(arg(args, 0)?),
+        "Osc1PulseWidth" => AudioMessage::Osc1PulseWidth(arg(args, 0)?),
+        "Osc1FmIndex" => AudioMessage::Osc1FmIndex(arg(args, 0)?),
+        "Osc1ThroughZero" => AudioMessage::Osc1ThroughZero(arg(args, 0)?),
+        "Osc1Unison" => AudioMessage::Osc1Unison(arg(args, 0)?),
+        "Osc1Detune" => AudioMessage::Osc1Detune(arg(args, 0)?),
+        "Osc1Spread" => AudioMessage::Osc1Spread(arg(args, 0)?),
+        // Osc2
+        "Osc2Freq" => AudioMessage::Osc2Freq(arg(args, 0)?),
+        "Osc2Phase" => AudioMessage::Osc2Phase(arg(args, 0)?),
+        "Osc2Level" => AudioMessage::Osc2Level(arg(args, 0)?),
+        "Osc2FmIndex" => AudioMessage::Osc2FmIndex(arg(args, 0)?),
+        "Osc2ThroughZero" => AudioMessage::Osc2ThroughZero(arg(args, 0)?),
+        "Osc2Unison" => AudioMessage::Osc2Unison(arg(args, 0)?),
+        "Osc2Detune" => AudioMessage::Osc2Detune(arg(args, 0)?),
+        "Osc2Spread" => AudioMessage::Osc2Spread(arg(args, 0)?),
+        "Osc2Position" => AudioMessage::Osc2Position(arg(args, 0)?),
+        "Osc2Interpolation" => AudioMessage::Osc2Interpolation(parse_interpolation(args)?),
+        // Lfo1
+        "Lfo1Shape" => AudioMessage::Lfo1Shape(parse_shape(args)?),
+        "Lfo1Freq" => AudioMessage::Lfo1Freq(arg(args, 0)?),
+        // Lfo2
+        "Lfo2Shape" => AudioMessage::Lfo2Shape(parse_shape(args)?),
+        "Lfo2Freq" => AudioMessage::Lfo2Freq(arg(args, 0)?),
+        // Noise
+        "NoiseLevel" => AudioMessage::NoiseLevel(arg(args, 0)?),
+        "NoiseShared" => AudioMessage::NoiseShared(arg(args, 0)?),
+        // Filters
+        "Filter1Freq" => AudioMessage::Filter1Freq(arg(args, 0)?),
+        "Filter1Resonance" => AudioMessage::Filter1Resonance(arg(args, 0)?),
+        "Filter1Mode" => AudioMessage::Filter1Mode(parse_filter_mode(args)?),
+        "Filter1Keytrack" => AudioMessage::Filter1Keytrack(arg(args, 0)?),
+        "Filter1CutoffAmount" => AudioMessage::Filter1CutoffAmount(arg(args, 0)?),
+        "Filter2Freq" => AudioMessage::Filter2Freq(arg(args, 0)?),
+        "Filter2Resonance" => AudioMessage::Filter2Resonance(arg(args, 0)?),
+        "Filter2Mode" => AudioMessage::Filter2Mode(parse_filter_mode(args)?),
+        "Filter2Keytrack" => AudioMessage::Filter2Keytrack(arg(args, 0)?),
+        "Filter2CutoffAmount" => AudioMessage::Filter2CutoffAmount(arg(args, 0)?),
+        // Ladder
+        "LadderFreq" => AudioMessage::LadderFreq(arg(args, 0)?),
+        "LadderResonance" => AudioMessage::LadderResonance(arg(args, 0)?),
+        "LadderDrive" => AudioMessage::LadderDrive(arg(args, 0)?),
+        // Env1
+        "Env1Attack" => AudioMessage::Env1Attack(arg(args, 0)?),
+        "Env1Decay" => AudioMessage::Env1Decay(arg(args, 0)?),
+        "Env1Release" => AudioMessage::Env1Release(arg(args, 0)?),
+        "Env1Sustain" => AudioMessage::Env1Sustain(arg(args, 0)?),
+        // Env2
+        "Env2Attack" => AudioMessage::Env2Attack(arg(args, 0)?),
+        "Env2Decay" => AudioMessage::Env2Decay(arg(args, 0)?),
+        "Env2Release" => AudioMessage::Env2Release(arg(args, 0)?),
+        "Env2Sustain" => AudioMessage::Env2Sustain(arg(args, 0)?),
+        // Env3
+        "Env3Attack" => AudioMessage::Env3Attack(arg(args, 0)?),
+        "Env3Decay" => AudioMessage::Env3Decay(arg(args, 0)?),
+        "Env3Release" => AudioMessage::Env3Release(arg(args, 0)?),
+        "Env3Sustain" => AudioMessage::Env3Sustain(arg(args, 0)?),
+        // Effects
+        "DistDrive" => AudioMessage::DistDrive(arg(args, 0)?),
+        "DistWet" => AudioMessage::DistWet(arg(args, 0)?),
+        "DelayFeedback" => AudioMessage::DelayFeedback(arg(args, 0)?),
+        "DelayTime" => AudioMessage::DelayTime(arg(args, 0)?),
+        "DelayWet" => AudioMessage::DelayWet(arg(args, 0)?),
+        "ReverbDamp" => AudioMessage::ReverbDamp(arg(args, 0)?),
+        "ReverbSpread" => AudioMessage::ReverbSpread(arg(args, 0)?),
+        "ReverbWet" => AudioMessage::ReverbWet(arg(args, 0)?),
+        "ReverbSpace" => AudioMessage::ReverbSpace(arg(args, 0)?),
+        // Master
+        "MasterGain" => AudioMessage::MasterGain(arg(args, 0)?),
+        // Midi, the first KeyPress argument is the note and the second the velocity
+        "KeyPress" => AudioMessage::KeyPress(arg(args, 0)?, arg(args, 1)?),
+        "KeyRelease" => AudioMessage::KeyRelease(arg(args, 0)?),
+        "PedalPress" => AudioMessage::PedalPress,
+        "PedalRelease" => AudioMessage::PedalRelease,
+        // Modules, ports are given as module id and jack block, e.g. `PortConnection 11 0 7 0`
+        "AddModule" => AudioMessage::AddModule(arg(args, 0)?, parse_module_kind(args)?.build()),
+        "RemoveModule" => AudioMessage::RemoveModule(arg(args, 0)?),
+        "ModuleParameter" => AudioMessage::ModuleParameter(arg(args, 0)?, arg(args, 1)?, arg(args, 2)?),
+        // Cables
+        "CableConnection" => AudioMessage::CableConnection(parse_input_jack(args)?, parse_output_jack(args)?),
+        "PortConnection" => AudioMessage::PortConnection(
+            PortId::new(arg(args, 0)?, arg(args, 1)?),
+            PortId::new(arg(args, 2)?, arg(args, 3)?),
+        ),
+        "CableAttenuation" => AudioMessage::CableAttenuation(arg(args, 0)?, arg(args, 1)?),
+        "CableRemove" => AudioMessage::CableRemove(arg(args, 0)?),
+        // Processing
+        "SampleAccurateFeedback" => AudioMessage::SampleAccurateFeedback(arg(args, 0)?),
+        "ParameterSmoothing" => AudioMessage::ParameterSmoothing(arg(args, 0)?),
+        _ => return Err(format!("Unknown message '{name}'")),
+    };
+    Ok(message)
+}
+
+fn arg<T: std::str::FromStr>(args: &[&str], index: usize) -> Result<T, String> {
+    let raw = args.get(index).ok_or_else(|| format!("Missing argument {}", index + 1))?;
+    raw.parse().map_err(|_| format!("Invalid argument '{raw}'"))
+}
+
+fn parse_shape(args: &[&str]) -> Result<WaveShape, String> {
+    match args.first() {
+        Some(&"Saw") => Ok(WaveShape::Saw),
+        Some(&"Sine") => Ok(WaveShape::Sine),
+        Some(&"Square") => Ok(WaveShape::Square),
+        Some(&"Triangle") => Ok(WaveShape::Triangle),
+        Some(shape) => Err(format!("Unknown wave shape '{shape}'")),
+        None => Err(String::from("Missing wave shape")),
+    }
+}
+
+fn parse_filter_mode(args: &[&str]) -> Result<FilterMode, String> {
+    match args.first() {
+        Some(&"Lowpass") => Ok(FilterMode::Lowpass),
+        Some(&"Highpass") => Ok(FilterMode::Highpass),
+        Some(&"Bandpass") => Ok(FilterMode::Bandpass),
+        Some(&"Notch") => Ok(FilterMode::Notch),
+        Some(&"Peak") => Ok(FilterMode::Peak),
+        Some(mode) => Err(format!("Unknown filter mode '{mode}'")),
+        None => Err(String::from("Missing filter mode")),
+    }
+}
+
+fn parse_interpolation(args: &[&str]) -> Result<Interpolation, String> {
+    match args.first() {
+        Some(&"Linear") => Ok(Interpolation::Linear),
+        Some(&"Hermite") => Ok(Interpolation::Hermite),
+        Some(&"Sinc") => Ok(Interpolation::Sinc),
+        Some(interpolation) => Err(format!("Unknown interpolation '{interpolation}'")),
+        None => Err(String::from("Missing interpolation")),
+    }
+}
+
+fn parse_module_kind(args: &[&str]) -> Result<ModuleKind, String> {
+    match args.get(1) {
+        Some(&"Analog") => Ok(ModuleKind::Analog),
+        Some(&"Wavetable") => Ok(ModuleKind::Wavetable),
+        Some(&"Envelope") => Ok(ModuleKind::Envelope),
+        Some(&"Filter") => Ok(ModuleKind::Filter),
+        Some(&"Ladder") => Ok(ModuleKind::Ladder),
+        Some(&"Lfo") => Ok(ModuleKind::Lfo),
+        Some(&"Noise") => Ok(ModuleKind::Noise),
+        Some(kind) => Err(format!("Unknown module kind '{kind}'")),
+        None => Err(String::from("Missing module kind")),
+    }
+}
+
+fn parse_input_jack(args: &[&str]) -> Result<InputJack, String> {
+    let jack = match args.first().copied().unwrap_or_default() {
+        "Osc1Freq" => InputJack::Osc1Freq,
+        "Osc1Phase" => InputJack::Osc1Phase,
+        "Osc1Level" => InputJack::Osc1Level,
+        "Osc1Amp" => InputJack::Osc1Amp,
+        "Osc1PulseWidth" => InputJack::Osc1PulseWidth,
+        "Osc1Fm" => InputJack::Osc1Fm,
+        "Osc2Freq" => InputJack::Osc2Freq,
+        "Osc2Phase" => InputJack::Osc2Phase,
+        "Osc2Level" => InputJack::Osc2Level,
+        "Osc2Amp" => InputJack::Osc2Amp,
+        "Osc2Sync" => InputJack::Osc2Sync,
+        "Osc2Fm" => InputJack::Osc2Fm,
+        "Osc2Position" => InputJack::Osc2Position,
+        "Filter1Cutoff" => InputJack::Filter1Cutoff,
+        "Filter1Resonance" => InputJack::Filter1Resonance,
+        "Filter1Value" => InputJack::Filter1Value,
+        "Filter2Cutoff" => InputJack::Filter2Cutoff,
+        "Filter2Resonance" => InputJack::Filter2Resonance,
+        "Filter2Value" => InputJack::Filter2Value,
+        "LadderCutoff" => InputJack::LadderCutoff,
+        "LadderResonance" => InputJack::LadderResonance,
+        "LadderDrive" => InputJack::LadderDrive,
+        "LadderValue" => InputJack::LadderValue,
+        "Env1Gate" => InputJack::Env1Gate,
+        "Env1Vel" => InputJack::Env1Vel,
+        "Env1Attack" => InputJack::Env1Attack,
+        "Env1Decay" => InputJack::Env1Decay,
+        "Env1Sustain" => InputJack::Env1Sustain,
+        "Env1Release" => InputJack::Env1Release,
+        "Env2Gate" => InputJack::Env2Gate,
+        "Env2Vel" => InputJack::Env2Vel,
+        "Env2Attack" => InputJack::Env2Attack,
+        "Env2Decay" => InputJack::Env2Decay,
+        "Env2Sustain" => InputJack::Env2Sustain,
+        "Env2Release" => InputJack::Env2Release,
+        "Env3Gate" => InputJack::Env3Gate,
+        "Env3Vel" => InputJack::Env3Vel,
+        "Env3Attack" => InputJack::Env3Attack,
+        "Env3Decay" => InputJack::Env3Decay,
+        "Env3Sustain" => InputJack::Env3Sustain,
+        "Env3Release" => InputJack::Env3Release,
+        "NoiseAmp" => InputJack::NoiseAmp,
+        "EffectsChain" => InputJack::EffectsChain,
+        "EffectsChainSide" => InputJack::EffectsChainSide,
+        name => return Err(format!("Unknown input jack '{name}'")),
+    };
+    Ok(jack)
+}
+
+fn parse_output_jack(args: &[&str]) -> Result<OutputJack, String> {
+    let jack = match args.get(1).copied().unwrap_or_default() {
+        "MidiDC" => OutputJack::MidiDC,
+        "MidiGate" => OutputJack::MidiGate,
+        "MidiNote" => OutputJack::MidiNote,
+        "MidiVelocity" => OutputJack::MidiVelocity,
+        "Osc1Value" => OutputJack::Osc1Value,
+        "Osc1Sync" => OutputJack::Osc1Sync,
+        "Osc1Side" => OutputJack::Osc1Side,
+        "Osc2Value" => OutputJack::Osc2Value,
+        "Osc2Side" => OutputJack::Osc2Side,
+        "Filter1Value" => OutputJack::Filter1Value,
+        "Filter2Value" => OutputJack::Filter2Value,
+        "LadderValue" => OutputJack::LadderValue,
+        "Env1Value" => OutputJack::Env1Value,
+        "Env2Value" => OutputJack::Env2Value,
+        "Env3Value" => OutputJack::Env3Value,
+        "Lfo1Value" => OutputJack::Lfo1Value,
+        "Lfo2Value" => OutputJack::Lfo2Value,
+        "NoiseWhite" => OutputJack::NoiseWhite,
+        "NoisePink" => OutputJack::NoisePink,
+        "NoiseBrown" => OutputJack::NoiseBrown,
+        name => return Err(format!("Unknown output jack '{name}'")),
+    };
+    Ok(jack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(score: &str) -> String {
+        match parse_score(score) {
+            Ok(_) => panic!("'{score}' parsed"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn parses_events_in_order() {
+        let score = "# comment\n\n0.0 KeyPress 60 100\n  0.5 Osc1Shape Square  \n1.0 KeyRelease 60\n";
+        let events = parse_score(score).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].time, 0.0);
+        assert!(matches!(events[0].message, AudioMessage::KeyPress(60, 100)));
+        assert!(matches!(events[1].message, AudioMessage::Osc1Shape(WaveShape::Square)));
+        assert_eq!(events[2].time, 1.0);
+        assert!(matches!(events[2].message, AudioMessage::KeyRelease(60)));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(parse_error("0.0 KeyPress 60 100\n\nsoon KeyRelease 60"), "Score line 3: Invalid time 'soon': invalid float literal");
+        assert_eq!(parse_error("# comment\n1.0"), "Score line 2: Missing message");
+        assert!(parse_error("0.0 Osc1Bogus 1.0").starts_with("Score line 1: "));
+        assert!(parse_error("0.0 KeyPress").starts_with("Score line 1: "));
+        assert!(parse_error("0.0 KeyPress sixty 100").starts_with("Score line 1: "));
+        assert_eq!(parse_error("0.0 Osc1Shape Circle"), "Score line 1: Unknown wave shape 'Circle'");
+        assert_eq!(parse_error("0.0 Filter1Mode"), "Score line 1: Missing filter mode");
+    }
+
+    #[test]
+    fn empty_score_has_no_events() {
+        assert!(parse_score("").unwrap().is_empty());
+        assert!(parse_score("# only a comment\n   \n").unwrap().is_empty());
+    }
+}
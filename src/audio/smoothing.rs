@@ -0,0 +1,190 @@
+use crate::audio::module::ModuleId;
+
+pub const DEFAULT_SMOOTHING_TIME: f32 = 0.02;
+const MAX_SMOOTHED_PARAMETERS: usize = 256;
+/// Longest chunk rendered with one value while a ramp runs
+const RAMP_CHUNK_SIZE: usize = 8;
+
+/// Linear ramp towards a target over a fixed number of samples
+#[derive(Clone, Copy, Debug)]
+pub struct Ramp {
+    value: f32,
+    target: f32,
+    step: f32,
+    remaining: usize,
+}
+
+impl Ramp {
+    pub fn new(value: f32) -> Self {
+        Self {
+            value,
+            target: value,
+            step: 0.0,
+            remaining: 0,
+        }
+    }
+
+    pub fn set_target(&mut self, target: f32, samples: usize) {
+        self.target = target;
+        self.remaining = samples;
+        if samples == 0 {
+            self.value = target;
+            self.step = 0.0;
+        } else {
+            self.step = (target - self.value) / samples as f32;
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Value `offset` samples after the current one
+    #[inline(always)]
+    pub fn value_at(&self, offset: usize) -> f32 {
+        if offset < self.remaining {
+            self.value + self.step * offset as f32
+        } else {
+            self.target
+        }
+    }
+
+    pub fn is_ramping(&self) -> bool {
+        self.remaining > 0
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    pub fn advance(&mut self, samples: usize) {
+        if samples >= self.remaining {
+            self.value = self.target;
+            self.remaining = 0;
+        } else {
+            self.value += self.step * samples as f32;
+            self.remaining -= samples;
+        }
+    }
+}
+
+struct SmoothedParameter {
+    module: ModuleId,
+    parameter: usize,
+    ramp: Ramp,
+    dirty: bool,
+}
+
+/// Ramps knob values so sweeping a knob does not step the parameter once per buffer
+///
+/// A parameter nobody registered applies its first value as is, since there is nothing to ramp from yet.
+pub struct ParameterSmoother {
+    parameters: Vec<SmoothedParameter>,
+    ramp_samples: usize,
+}
+
+impl ParameterSmoother {
+    pub fn new(ramp_time: f32, sample_rate: f64) -> Self {
+        Self {
+            parameters: Vec::with_capacity(MAX_SMOOTHED_PARAMETERS),
+            ramp_samples: (ramp_time as f64 * sample_rate) as usize,
+        }
+    }
+
+    pub fn set_ramp_time(&mut self, ramp_time: f32, sample_rate: f64) {
+        self.ramp_samples = (ramp_time.max(0.0) as f64 * sample_rate) as usize;
+    }
+
+    pub fn ramp_samples(&self) -> usize {
+        self.ramp_samples
+    }
+
+    /// Seeds the value a parameter's first change ramps from, without applying it
+    pub fn register(&mut self, module: ModuleId, parameter: usize, value: f32) {
+        let existing = self.parameters.iter_mut()
+            .find(|smoothed| smoothed.module == module && smoothed.parameter == parameter);
+        if let Some(smoothed) = existing {
+            smoothed.ramp = Ramp::new(value);
+            smoothed.dirty = false;
+        } else if self.parameters.len() < MAX_SMOOTHED_PARAMETERS {
+            self.parameters.push(SmoothedParameter { module, parameter, ramp: Ramp::new(value), dirty: false });
+        }
+    }
+
+    /// Returns false when every slot is taken, the caller then has to apply the value itself
+    pub fn set(&mut self, module: ModuleId, parameter: usize, value: f32) -> bool {
+        let existing = self.parameters.iter_mut()
+            .find(|smoothed| smoothed.module == module && smoothed.parameter == parameter);
+        if let Some(smoothed) = existing {
+            smoothed.ramp.set_target(value, self.ramp_samples);
+            smoothed.dirty = true;
+        } else if self.parameters.len() < MAX_SMOOTHED_PARAMETERS {
+            self.parameters.push(SmoothedParameter { module, parameter, ramp: Ramp::new(value), dirty: true });
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Drops the state of a removed or replaced module
+    pub fn forget(&mut self, module: ModuleId) {
+        self.parameters.retain(|smoothed| smoothed.module != module);
+    }
+
+    /// Length of the next chunk, short while ramping and ending where a ramp ends so it lands on its target
+    pub fn chunk_len(&self, max: usize) -> usize {
+        self.parameters.iter()
+            .filter(|smoothed| smoothed.ramp.is_ramping())
+            .fold(max, |len, smoothed| len.min(RAMP_CHUNK_SIZE).min(smoothed.ramp.remaining()))
+    }
+
+    /// Applies the current value of every changed parameter, then moves the ramps on by `samples`
+    pub fn advance(&mut self, samples: usize, mut apply: impl FnMut(ModuleId, usize, f32)) {
+        for smoothed in self.parameters.iter_mut().filter(|smoothed| smoothed.dirty) {
+            apply(smoothed.module, smoothed.parameter, smoothed.ramp.value());
+            smoothed.dirty = smoothed.ramp.is_ramping();
+            smoothed.ramp.advance(samples);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values applied per chunk while rendering `samples` samples
+    fn applied_values(smoother: &mut ParameterSmoother, samples: usize) -> Vec<(usize, f32)> {
+        let mut applied = Vec::new();
+        let mut position = 0;
+        while position < samples {
+            let len = smoother.chunk_len(samples - position);
+            smoother.advance(len, |_, _, value| applied.push((position, value)));
+            position += len;
+        }
+        applied
+    }
+
+    #[test]
+    fn ramps_in_short_chunks_and_lands_on_target() {
+        let mut smoother = ParameterSmoother::new(0.0, 48000.0);
+        smoother.set(0, 0, 0.0);
+        smoother.ramp_samples = 20;
+        smoother.set(0, 0, 1.0);
+
+        let applied = applied_values(&mut smoother, 64);
+        assert_eq!(applied, [(0, 0.0), (8, 0.4), (16, 0.8), (20, 1.0)]);
+        assert_eq!(smoother.chunk_len(64), 64);
+    }
+
+    #[test]
+    fn first_set_ramps_from_the_registered_value() {
+        let mut smoother = ParameterSmoother::new(0.0, 48000.0);
+        smoother.ramp_samples = 20;
+        smoother.register(0, 0, 0.0);
+        assert!(applied_values(&mut smoother, 64).is_empty());
+
+        smoother.set(0, 0, 1.0);
+        let applied = applied_values(&mut smoother, 64);
+        assert_eq!(applied, [(0, 0.0), (8, 0.4), (16, 0.8), (20, 1.0)]);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{mem::MaybeUninit, ops::{Deref, DerefMut}};
+use std::{cell::UnsafeCell, mem::MaybeUninit, ops::{Deref, DerefMut}, sync::{atomic::{AtomicUsize, Ordering}, Arc}};
 
 use sdl3::render::{FPoint, FRect};
 
@@ -26,6 +26,14 @@ impl <T, const MAX: usize> ComponentVec<T, MAX> {
         Ok(())
     }
 
+    pub fn pop(&mut self) -> Option<T> {
+        if self.count == 0 {
+            return None;
+        }
+        self.count -= 1;
+        Some(unsafe { self.components[self.count].assume_init_read() })
+    }
+
     pub fn remove(&mut self, index: usize) -> T {
         if index >= self.count {
             panic!("Out of bounds remove");
@@ -56,6 +64,68 @@ impl <T, const MAX: usize> DerefMut for ComponentVec<T, MAX> {
     }
 }
 
+/// Fixed capacity single producer single consumer queue, pushing and popping never block or allocate
+pub fn ring_buffer<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    let ring = Arc::new(RingBuffer {
+        slots: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+    (Producer(ring.clone()), Consumer(ring))
+}
+
+struct RingBuffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Total pops, only written by the consumer
+    head: AtomicUsize,
+    /// Total pushes, only written by the producer
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for RingBuffer<T> {}
+unsafe impl<T: Send> Sync for RingBuffer<T> {}
+
+impl <T> Drop for RingBuffer<T> {
+    fn drop(&mut self) {
+        let head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        for i in head..tail {
+            unsafe { self.slots[i % self.slots.len()].get_mut().assume_init_drop() };
+        }
+    }
+}
+
+pub struct Producer<T>(Arc<RingBuffer<T>>);
+
+impl <T> Producer<T> {
+    /// Hands the value back if the queue is full
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let tail = self.0.tail.load(Ordering::Relaxed);
+        let head = self.0.head.load(Ordering::Acquire);
+        if tail - head == self.0.slots.len() {
+            return Err(value);
+        }
+        unsafe { (*self.0.slots[tail % self.0.slots.len()].get()).write(value) };
+        self.0.tail.store(tail + 1, Ordering::Release);
+        Ok(())
+    }
+}
+
+pub struct Consumer<T>(Arc<RingBuffer<T>>);
+
+impl <T> Consumer<T> {
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.0.head.load(Ordering::Relaxed);
+        let tail = self.0.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let value = unsafe { (*self.0.slots[head % self.0.slots.len()].get()).assume_init_read() };
+        self.0.head.store(head + 1, Ordering::Release);
+        Some(value)
+    }
+}
+
 pub fn point_in_frect(rect: &FRect, x: f32, y: f32) -> bool {
     x >= rect.x && x <= rect.x + rect.w && y >= rect.y && y <= rect.y + rect.h
 }
@@ -18,8 +18,9 @@ mod toggleable;
 mod meters;
 
 use core::f32;
-use std::sync::mpsc::Sender;
+use std::path::{Path, PathBuf};
 use realfft::RealFftPlanner;
+use sdl3::keyboard::{Keycode, Mod};
 use sdl3::pixels::PixelFormat;
 use sdl3::sys::pixels::SDL_PIXELFORMAT_ABGR8888;
 use sdl3::video::WindowContext;
@@ -27,12 +28,12 @@ use sdl3::{video::Window};
 use sdl3::render::{Canvas, FPoint, FRect, Texture, TextureCreator};
 
 use crate::audio::{AudioMessage, InputJack, OutputJack};
-use crate::common::ComponentVec;
+use crate::common::{ComponentVec, Producer};
 use crate::gui::animation::Animation;
 use crate::gui::drawable::{Drawables, OnReleaseBehavior};
 use crate::gui::jacks::JackData;
 use crate::gui::meters::Meters;
-use crate::gui::toggleable::Toggleables;
+use crate::gui::toggleable::{OnToggleBehavior, Toggleables};
 use crate::gui::dragable::{DragType, Dragables, OnDragBehavior};
 
 const FACEPLATE_TEXTURE: usize = 0;
@@ -43,19 +44,27 @@ const KNOB_4_TEXTURE: usize = 4;
 const SLIDER_CABLE_TEXTURE: usize = 5;
 const SLIDER_128_TEXTURE: usize = 6;
 const METER_MASTER_TEXTURE: usize = 7;
-const TEXTURE_COUNT: usize = 8;
+const KNOB_5_TEXTURE: usize = 8;
+const KNOB_2_TEXTURE: usize = 9;
+const KNOB_3_TEXTURE: usize = 10;
+const TEXTURE_COUNT: usize = 11;
 
 const JACK_WIDTH: f32 = 32.0;
 const JACK_HEIGHT: f32 = 32.0;
+/// Knobs squeezed into gaps too small for a full size one
+const SMALL_KNOB_SIZE: f32 = 48.0;
 
 const KNOB_128_ANIMATION: Animation = Animation::new_comptime(KNOB_128_TEXTURE, 128, 64.0, 64.0);
 const KNOB_4_ANIMATION: Animation = Animation::new_comptime(KNOB_4_TEXTURE, 4, 64.0, 64.0);
+const KNOB_5_ANIMATION: Animation = Animation::new_comptime(KNOB_5_TEXTURE, 5, 64.0, 64.0);
+const KNOB_2_ANIMATION: Animation = Animation::new_comptime(KNOB_2_TEXTURE, 2, 64.0, 64.0);
+const KNOB_3_ANIMATION: Animation = Animation::new_comptime(KNOB_3_TEXTURE, 3, 64.0, 64.0);
 const SLIDER_CABLE_ANIMATION: Animation = Animation::new_comptime(SLIDER_CABLE_TEXTURE, 201, 64.0, 32.0);
 const METER_MASTER_ANIMATION: Animation = Animation::new_comptime(METER_MASTER_TEXTURE, 31, 35.0, 120.0);
 const SLIDER_128_ANIMATION: Animation = Animation::new_comptime(SLIDER_128_TEXTURE, 128, 35.0, 90.0);
 
 pub struct Gui<'a> {
-    audio_channel: Sender<AudioMessage>,
+    audio_channel: Producer<AudioMessage>,
 
     mouse_pos: FPoint,
     //text_msg: Option<fn (String) -> GuiMessage>,
@@ -75,7 +84,7 @@ pub struct Gui<'a> {
 }
 
 impl <'a> Gui <'a> {
-    pub fn new(audio_channel: Sender<AudioMessage>, texture_creator: &'a TextureCreator<WindowContext>) -> Self {
+    pub fn new(audio_channel: Producer<AudioMessage>, texture_creator: &'a TextureCreator<WindowContext>) -> Self {
         let mut fft_planner = RealFftPlanner::new();
         Self {
             audio_channel,
@@ -101,13 +110,18 @@ impl <'a> Gui <'a> {
         self.load_texture(include_bytes!("../assets/slider_cable201_64x32.png"));
         self.load_texture(include_bytes!("../assets/slider_128_35x90.png"));
         self.load_texture(include_bytes!("../assets/meter_master31_35x120.png"));
+        self.load_texture(include_bytes!("../assets/knob_basic5.png"));
+        self.load_texture(include_bytes!("../assets/knob_basic2.png"));
+        self.load_texture(include_bytes!("../assets/knob_basic3.png"));
 
         self.init_osc1();
         self.init_osc2();
         self.init_midi();
         self.init_lfos();
+        self.init_noise();
         self.init_envs();
         self.init_filters();
+        self.init_ladder();
         self.init_effects();
 
         let left_master_meter = FRect::new(
@@ -125,7 +139,22 @@ impl <'a> Gui <'a> {
         self.meters.init(left_master_meter, right_master_meter);
 
         // OSC 2
-        self.drawables.spawn(FRect::new(502.0, 16.0, 256.0, 256.0), OnReleaseBehavior::Osc2WavetableTimeDomain).unwrap();
+        self.drawables.spawn(FRect::new(502.0, 16.0, 256.0, 256.0), OnReleaseBehavior::Osc2WavetableTimeDomain, true).unwrap();
+        self.drawables.spawn(FRect::new(502.0, 16.0, 256.0, 256.0), OnReleaseBehavior::Osc2WavetableHarmonics, false).unwrap();
+        // Switches the drawable between drawing the wave and drawing its harmonics
+        self.toggleables.spawn(
+            FRect::new(790.0, 156.0, 32.0, 32.0),
+            OnToggleBehavior::Osc2WavetableView,
+            0,
+            KNOB_2_ANIMATION,
+        ).unwrap();
+    }
+
+    /// Runs once per frame after the events
+    pub fn update(&mut self) {
+        jacks::update_system(&mut self.audio_channel, &mut self.jacks);
+        dragable::update_system(&mut self.audio_channel, &mut self.dragables);
+        toggleable::update_system(&mut self.audio_channel, &mut self.toggleables);
     }
 
     pub fn render(&mut self, canvas: &mut Canvas<Window>) -> Result<(), sdl3::Error> {
@@ -148,14 +177,19 @@ impl <'a> Gui <'a> {
 
     pub fn left_mouse_down(&mut self, x: f32, y: f32, clicks: u8) {
         dragable::on_left_down_system(&mut self.audio_channel, &mut self.dragables, x, y, clicks);
-        toggleable::on_left_down_system(&mut self.audio_channel, &mut self.toggleables, x, y, clicks);
+        if let Some((OnToggleBehavior::Osc2WavetableView, state)) = toggleable::on_left_down_system(&mut self.audio_channel, &mut self.toggleables, x, y, clicks) {
+            let view = if state == 0 { OnReleaseBehavior::Osc2WavetableTimeDomain } else { OnReleaseBehavior::Osc2WavetableHarmonics };
+            drawable::show_system(&mut self.drawables, view);
+        }
         drawable::on_left_down_system(&mut self.drawables, x, y);
         jacks::on_left_down_system(&mut self.jacks, x, y);
     }
 
     pub fn left_mouse_up(&mut self, _clicks: u8)  {
         dragable::on_left_release_system(&mut self.dragables);
-        drawable::on_left_release_system(&mut self.audio_channel, &mut self.drawables);
+        if let Err(err) = drawable::on_left_release_system(&mut self.audio_channel, &mut self.drawables) {
+            eprintln!("{err}");
+        }
         jacks::on_left_release_system(&mut self.audio_channel, &mut self.jacks, self.mouse_pos);
     }
 
@@ -178,6 +212,28 @@ impl <'a> Gui <'a> {
 
     }
 
+    /// Ctrl+E exports Osc2's wavetable next to where the synth was started, with Shift it keeps every variation
+    pub fn key_down(&mut self, keycode: Keycode, keymod: Mod) {
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        if keycode == Keycode::E && ctrl {
+            let Some(path) = (1..).map(|n| PathBuf::from(format!("osc2_wavetable_{n}.wav"))).find(|path| !path.exists()) else {
+                return;
+            };
+            match drawable::export_wavetable_system(&self.drawables, &path, shift) {
+                Ok(()) => println!("Exported Osc2's wavetable to '{}'", path.display()),
+                Err(err) => eprintln!("{err}"),
+            }
+        }
+    }
+
+    /// Dropped WAV files become Osc2's wavetable
+    pub fn drop_file(&mut self, filename: String) {
+        if let Err(err) = drawable::load_wavetable_system(&mut self.audio_channel, &mut self.drawables, Path::new(&filename)) {
+            eprintln!("{err}");
+        }
+    }
+
     pub fn master_meter(&mut self, left: f32, right: f32) {
         let left_level = left.sqrt();
         let right_level = right.sqrt();
@@ -229,6 +285,51 @@ impl <'a> Gui <'a> {
             dragable::OnDoubleClickBehavior::SetTo(0.5),
             KNOB_128_ANIMATION,
         ).unwrap();
+        self.dragables.spawn(
+            FRect::new(264.0, 18.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.5,
+            (DragType::VERTICAL, OnDragBehavior::Osc1PulseWidth),
+            dragable::OnDoubleClickBehavior::SetTo(0.5),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(214.0, 150.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc1FmIndex),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+
+        // Through zero FM, off turns the frequency around at 0 Hz
+        self.toggleables.spawn(
+            FRect::new(230.0, 18.0, 32.0, 32.0),
+            OnToggleBehavior::Osc1ThroughZero,
+            0,
+            KNOB_2_ANIMATION,
+        ).unwrap();
+
+        // Unison count, detune and stereo spread, in the top left corner of the MIDI panel
+        self.dragables.spawn(
+            FRect::new(424.0, 300.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc1Unison),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(470.0, 300.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc1Detune),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(516.0, 300.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc1Spread),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
 
         // Inputs
         self.jacks.spawn_input(
@@ -247,12 +348,24 @@ impl <'a> Gui <'a> {
             FRect::new(436.0, 86.0, JACK_WIDTH, JACK_HEIGHT),
             InputJack::Osc1Level,
         ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(358.0, 86.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::Osc1PulseWidth,
+        ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(316.0, 150.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::Osc1Fm,
+        ).unwrap();
 
         // Output
         self.jacks.spawn_output(
             FRect::new(436.0, 190.0, JACK_WIDTH, JACK_HEIGHT),
             OutputJack::Osc1Value,
         ).unwrap();
+        self.jacks.spawn_output(
+            FRect::new(362.0, 226.0, JACK_WIDTH, JACK_HEIGHT),
+            OutputJack::Osc1Sync,
+        ).unwrap();
     }
 
     fn init_osc2(&mut self) {
@@ -278,6 +391,59 @@ impl <'a> Gui <'a> {
             dragable::OnDoubleClickBehavior::SetTo(0.0),
             KNOB_128_ANIMATION,
         ).unwrap();
+        self.dragables.spawn(
+            FRect::new(1186.0, 166.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc2FmIndex),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(1100.0, 46.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc2Position),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+
+        // Through zero FM, off turns the frequency around at 0 Hz
+        self.toggleables.spawn(
+            FRect::new(1202.0, 230.0, 32.0, 32.0),
+            OnToggleBehavior::Osc2ThroughZero,
+            0,
+            KNOB_2_ANIMATION,
+        ).unwrap();
+
+        // Interpolation, a click steps through linear, cubic Hermite and windowed sinc
+        self.toggleables.spawn(
+            FRect::new(990.0, 40.0, 32.0, 32.0),
+            OnToggleBehavior::Osc2Interpolation,
+            0,
+            KNOB_3_ANIMATION,
+        ).unwrap();
+
+        // Unison count, detune and stereo spread, in the bottom left corner of the MIDI panel
+        self.dragables.spawn(
+            FRect::new(424.0, 468.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc2Unison),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(470.0, 468.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc2Detune),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(516.0, 468.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Osc2Spread),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
 
         // Inputs
         self.jacks.spawn_input(
@@ -296,6 +462,18 @@ impl <'a> Gui <'a> {
             FRect::new(790.0, 86.0, JACK_WIDTH, JACK_HEIGHT),
             InputJack::Osc2Level,
         ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(1000.0, 192.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::Osc2Sync,
+        ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(1000.0, 150.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::Osc2Fm,
+        ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(1046.0, 192.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::Osc2Position,
+        ).unwrap();
 
         // Output
         self.jacks.spawn_output(
@@ -367,6 +545,46 @@ impl <'a> Gui <'a> {
         ).unwrap();
     }
 
+    /// Sits in the free space of the LFO panel between the frequency knobs and outputs
+    fn init_noise(&mut self) {
+        // Knob
+        self.dragables.spawn(
+            FRect::new(246.0, 424.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.5,
+            (DragType::VERTICAL, OnDragBehavior::NoiseLevel),
+            dragable::OnDoubleClickBehavior::SetTo(0.5),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+
+        // Every voice plays the same noise when on
+        self.toggleables.spawn(
+            FRect::new(374.0, 334.0, 32.0, 32.0),
+            OnToggleBehavior::NoiseShared,
+            0,
+            KNOB_2_ANIMATION,
+        ).unwrap();
+
+        // Input
+        self.jacks.spawn_input(
+            FRect::new(330.0, 334.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::NoiseAmp,
+        ).unwrap();
+
+        // Outputs
+        self.jacks.spawn_output(
+            FRect::new(240.0, 378.0, JACK_WIDTH, JACK_HEIGHT),
+            OutputJack::NoiseWhite,
+        ).unwrap();
+        self.jacks.spawn_output(
+            FRect::new(282.0, 378.0, JACK_WIDTH, JACK_HEIGHT),
+            OutputJack::NoisePink,
+        ).unwrap();
+        self.jacks.spawn_output(
+            FRect::new(324.0, 378.0, JACK_WIDTH, JACK_HEIGHT),
+            OutputJack::NoiseBrown,
+        ).unwrap();
+    }
+
     fn init_envs(&mut self) {
         const KNOB_SCALING: f32 = 1.0 / 3.0;
         let env_start_attack: f32 = (0.02f32 / 10.0).powf(KNOB_SCALING);
@@ -573,6 +791,50 @@ impl <'a> Gui <'a> {
             dragable::OnDoubleClickBehavior::SetTo(0.7),
             KNOB_128_ANIMATION,
         ).unwrap();
+        self.dragables.spawn(
+            FRect::new(1080.0, 326.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Filter1Resonance),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(1080.0, 414.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Filter2Resonance),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+
+        // Keytrack and cutoff jack amount, in the free corners of the MIDI panel beside each filter's row
+        self.dragables.spawn(
+            FRect::new(704.0, 296.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Filter1Keytrack),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(768.0, 296.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            1.0,
+            (DragType::VERTICAL, OnDragBehavior::Filter1CutoffAmount),
+            dragable::OnDoubleClickBehavior::SetTo(1.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(704.0, 460.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::Filter2Keytrack),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(768.0, 460.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            1.0,
+            (DragType::VERTICAL, OnDragBehavior::Filter2CutoffAmount),
+            dragable::OnDoubleClickBehavior::SetTo(1.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
 
         // Inputs
         self.jacks.spawn_input(
@@ -591,6 +853,28 @@ impl <'a> Gui <'a> {
             FRect::new(936.0, 428.0, JACK_WIDTH, JACK_HEIGHT),
             InputJack::Filter2Value,
         ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(1048.0, 344.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::Filter1Resonance,
+        ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(1048.0, 428.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::Filter2Resonance,
+        ).unwrap();
+
+        // Modes, a click steps through lowpass, highpass, bandpass, notch and peak
+        self.toggleables.spawn(
+            FRect::new(1218.0, 366.0, 32.0, 32.0),
+            OnToggleBehavior::Filter1Mode,
+            0,
+            KNOB_5_ANIMATION,
+        ).unwrap();
+        self.toggleables.spawn(
+            FRect::new(1218.0, 462.0, 32.0, 32.0),
+            OnToggleBehavior::Filter2Mode,
+            0,
+            KNOB_5_ANIMATION,
+        ).unwrap();
 
         // Outputs
         self.jacks.spawn_output(
@@ -603,6 +887,53 @@ impl <'a> Gui <'a> {
         ).unwrap();
     }
 
+    fn init_ladder(&mut self) {
+        // Cutoff, resonance and drive, under the title of the MIDI panel
+        self.dragables.spawn(
+            FRect::new(564.0, 328.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.7,
+            (DragType::VERTICAL, OnDragBehavior::LadderFreq),
+            dragable::OnDoubleClickBehavior::SetTo(0.7),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(610.0, 328.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::LadderResonance),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(656.0, 328.0, SMALL_KNOB_SIZE, SMALL_KNOB_SIZE),
+            0.0,
+            (DragType::VERTICAL, OnDragBehavior::LadderDrive),
+            dragable::OnDoubleClickBehavior::SetTo(0.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+
+        // Jacks, in the row between the two filters and under the same column labels
+        self.jacks.spawn_input(
+            FRect::new(936.0, 386.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::LadderValue,
+        ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(973.0, 386.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::LadderDrive,
+        ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(1010.0, 386.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::LadderCutoff,
+        ).unwrap();
+        self.jacks.spawn_input(
+            FRect::new(1048.0, 386.0, JACK_WIDTH, JACK_HEIGHT),
+            InputJack::LadderResonance,
+        ).unwrap();
+        self.jacks.spawn_output(
+            FRect::new(864.0, 386.0, JACK_WIDTH, JACK_HEIGHT),
+            OutputJack::LadderValue,
+        ).unwrap();
+    }
+
     fn init_effects(&mut self) {
         // Input
         self.jacks.spawn_input(
@@ -664,6 +995,20 @@ impl <'a> Gui <'a> {
             dragable::OnDoubleClickBehavior::SetTo(0.0),
             KNOB_128_ANIMATION,
         ).unwrap();
+        self.dragables.spawn(
+            FRect::new(1092.0, 586.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            1.0,
+            (DragType::VERTICAL, OnDragBehavior::EffectReverbSpace),
+            dragable::OnDoubleClickBehavior::SetTo(1.0),
+            KNOB_128_ANIMATION,
+        ).unwrap();
+        self.dragables.spawn(
+            FRect::new(1092.0, 676.0, KNOB_128_ANIMATION.width(), KNOB_128_ANIMATION.height()),
+            0.23,
+            (DragType::VERTICAL, OnDragBehavior::EffectReverbSpread),
+            dragable::OnDoubleClickBehavior::SetTo(0.23),
+            KNOB_128_ANIMATION,
+        ).unwrap();
 
         // Master
         self.dragables.spawn(
@@ -1,8 +1,6 @@
-use std::sync::mpsc;
-
 use sdl3::{render::{Canvas, FRect, Texture}, video::Window};
 
-use crate::{audio::{AudioMessage, WaveShape}, common::{point_in_frect, ComponentVec}, gui::animation::Animation};
+use crate::{audio::{AudioMessage, WaveShape}, common::{point_in_frect, ComponentVec, Producer}, gui::animation::Animation};
 
 const MAX_DRAGABLE_COUNT: usize = 228;
 
@@ -21,15 +19,35 @@ pub enum OnDragBehavior {
     Osc1Shape,
     Osc1Level,
     Osc1Phase,
+    Osc1PulseWidth,
+    Osc1FmIndex,
+    Osc1Unison,
+    Osc1Detune,
+    Osc1Spread,
     Osc2Level,
+    Osc2FmIndex,
+    Osc2Unison,
+    Osc2Detune,
+    Osc2Spread,
+    Osc2Position,
     Osc2Freq,
     Osc2Phase,
     Lfo1Shape,
     Lfo1Freq,
     Lfo2Shape,
     Lfo2Freq,
+    NoiseLevel,
     Filter1Freq,
+    Filter1Resonance,
+    Filter1Keytrack,
+    Filter1CutoffAmount,
     Filter2Freq,
+    Filter2Resonance,
+    Filter2Keytrack,
+    Filter2CutoffAmount,
+    LadderFreq,
+    LadderResonance,
+    LadderDrive,
     Env1Attack,
     Env1Decay,
     Env1Release,
@@ -66,6 +84,7 @@ pub struct Dragables {
     on_drag: ComponentVec<(DragType, OnDragBehavior), MAX_DRAGABLE_COUNT>,
     on_double_click: ComponentVec<OnDoubleClickBehavior, MAX_DRAGABLE_COUNT>,
     render: ComponentVec<Animation, MAX_DRAGABLE_COUNT>,
+    unsent: ComponentVec<Option<AudioMessage>, MAX_DRAGABLE_COUNT>,
 }
 
 impl Dragables {
@@ -77,6 +96,7 @@ impl Dragables {
             on_drag: ComponentVec::new(),
             on_double_click: ComponentVec::new(),
             render: ComponentVec::new(),
+            unsent: ComponentVec::new(),
         }
     }
 
@@ -93,11 +113,12 @@ impl Dragables {
         self.on_double_click.push(on_click)?;
         self.value.push(inital_value)?;
         self.render.push(animation)?;
+        self.unsent.push(None)?;
         Ok(())
     }
 }
 
-pub fn on_left_down_system(audio_channel: &mut mpsc::Sender<AudioMessage>, dragables: &mut Dragables, x: f32, y: f32, clicks: u8) {
+pub fn on_left_down_system(audio_channel: &mut Producer<AudioMessage>, dragables: &mut Dragables, x: f32, y: f32, clicks: u8) {
     for (i, rect) in dragables.rect.iter().enumerate() {
         if point_in_frect(rect, x, y) {
             if clicks == 2 {
@@ -105,7 +126,7 @@ pub fn on_left_down_system(audio_channel: &mut mpsc::Sender<AudioMessage>, draga
                     OnDoubleClickBehavior::SetTo(value) => {
                         let (_, on_drag) = dragables.on_drag[i];
                         let animation_frames = dragables.render[i].get_frame_count();
-                        on_drag_behavior(audio_channel, &mut dragables.value[i], on_drag, value, animation_frames);
+                        on_drag_behavior(audio_channel, &mut dragables.unsent[i], &mut dragables.value[i], on_drag, value, animation_frames);
                     },
                 }
             }
@@ -115,7 +136,7 @@ pub fn on_left_down_system(audio_channel: &mut mpsc::Sender<AudioMessage>, draga
     }
 }
 
-pub fn on_mouse_move_system(audio_channel: &mut mpsc::Sender<AudioMessage>, dragables: &mut Dragables, xrel: f32, yrel: f32) {
+pub fn on_mouse_move_system(audio_channel: &mut Producer<AudioMessage>, dragables: &mut Dragables, xrel: f32, yrel: f32) {
     if let Some((dragable_index, (drag_type, on_drag))) = dragables.dragging_info {
         let drag_amt = match drag_type {
             DragType::HORIZONTAL =>  xrel/200.0,
@@ -123,7 +144,7 @@ pub fn on_mouse_move_system(audio_channel: &mut mpsc::Sender<AudioMessage>, drag
         };
         let new_value = (dragables.value[dragable_index] + drag_amt).clamp(0.0, 1.0);
         let animation_frames = dragables.render[dragable_index].get_frame_count();
-        on_drag_behavior(audio_channel, &mut dragables.value[dragable_index], on_drag, new_value, animation_frames);
+        on_drag_behavior(audio_channel, &mut dragables.unsent[dragable_index], &mut dragables.value[dragable_index], on_drag, new_value, animation_frames);
     }
 }
 
@@ -131,6 +152,15 @@ pub fn on_left_release_system(dragables: &mut Dragables) {
     dragables.dragging_info = None;
 }
 
+/// Resends the knob values a full queue turned away, called once per frame
+pub fn update_system(audio_channel: &mut Producer<AudioMessage>, dragables: &mut Dragables) {
+    for unsent in dragables.unsent.iter_mut() {
+        if let Some(msg) = unsent.take() {
+            *unsent = audio_channel.push(msg).err();
+        }
+    }
+}
+
 pub fn render_system(canvas: &mut Canvas<Window>, textures: &[Texture], dragables: &Dragables) -> Result<(), sdl3::Error> {
     for (dst, (value, animation)) in dragables.rect.iter().zip(dragables.value.iter().zip(dragables.render.iter())) {
         let animation_frame = ((animation.get_frame_count() - 1) as f32 * value) as usize;
@@ -140,15 +170,27 @@ pub fn render_system(canvas: &mut Canvas<Window>, textures: &[Texture], dragable
     Ok(())
 }
 
-fn on_drag_behavior(audio_channel: &mut mpsc::Sender<AudioMessage>, value: &mut f32, on_drag: OnDragBehavior, new_value: f32, animation_frames: usize) {
+/// Frame value for knobs whose ends and centre have to be exact, like 100% keytrack or an ignored bipolar amount
+///
+/// Full travel sends 0.0 to 1.0, an even frame count has no middle frame so the one just past it sends 0.5.
+fn exact_value(frame: usize, animation_frames: usize) -> f32 {
+    let last_frame = animation_frames - 1;
+    if frame == animation_frames / 2 && frame < last_frame {
+        0.5
+    } else {
+        frame as f32 / last_frame as f32
+    }
+}
+
+fn on_drag_behavior(audio_channel: &mut Producer<AudioMessage>, unsent: &mut Option<AudioMessage>, value: &mut f32, on_drag: OnDragBehavior, new_value: f32, animation_frames: usize) {
     let old_frame = ((animation_frames - 1) as f32 * *value) as usize;
     let new_frame = (animation_frames - 1) as f32 * new_value;
     *value = new_value;
     if old_frame != new_frame as usize {
         let send_value = new_frame / animation_frames as f32;
-        let result = match on_drag {
+        let msg = match on_drag {
             // Osc1
-            OnDragBehavior::Osc1Freq => audio_channel.send(AudioMessage::Osc1Freq(send_value)),
+            OnDragBehavior::Osc1Freq => AudioMessage::Osc1Freq(send_value),
             OnDragBehavior::Osc1Shape => {
                 let shape = match (send_value * 4.0) as usize {
                     0 => WaveShape::Sine,
@@ -156,17 +198,27 @@ fn on_drag_behavior(audio_channel: &mut mpsc::Sender<AudioMessage>, value: &mut
                     2 => WaveShape::Square,
                     _ => WaveShape::Saw,
                 };
-                audio_channel.send(AudioMessage::Osc1Shape(shape))
+                AudioMessage::Osc1Shape(shape)
             },
-            OnDragBehavior::Osc1Level => audio_channel.send(AudioMessage::Osc1Level(send_value)),
-            OnDragBehavior::Osc1Phase => audio_channel.send(AudioMessage::Osc1Phase(send_value)),
+            OnDragBehavior::Osc1Level => AudioMessage::Osc1Level(send_value),
+            OnDragBehavior::Osc1Phase => AudioMessage::Osc1Phase(send_value),
+            OnDragBehavior::Osc1PulseWidth => AudioMessage::Osc1PulseWidth(send_value),
+            OnDragBehavior::Osc1FmIndex => AudioMessage::Osc1FmIndex(send_value),
+            OnDragBehavior::Osc1Unison => AudioMessage::Osc1Unison(send_value),
+            OnDragBehavior::Osc1Detune => AudioMessage::Osc1Detune(send_value),
+            OnDragBehavior::Osc1Spread => AudioMessage::Osc1Spread(send_value),
             // Osc2
-            OnDragBehavior::Osc2Freq => audio_channel.send(AudioMessage::Osc2Freq(send_value)),
-            OnDragBehavior::Osc2Level => audio_channel.send(AudioMessage::Osc2Level(send_value)),
-            OnDragBehavior::Osc2Phase => audio_channel.send(AudioMessage::Osc2Phase(send_value)),
+            OnDragBehavior::Osc2Freq => AudioMessage::Osc2Freq(send_value),
+            OnDragBehavior::Osc2Level => AudioMessage::Osc2Level(send_value),
+            OnDragBehavior::Osc2FmIndex => AudioMessage::Osc2FmIndex(send_value),
+            OnDragBehavior::Osc2Unison => AudioMessage::Osc2Unison(send_value),
+            OnDragBehavior::Osc2Detune => AudioMessage::Osc2Detune(send_value),
+            OnDragBehavior::Osc2Spread => AudioMessage::Osc2Spread(send_value),
+            OnDragBehavior::Osc2Position => AudioMessage::Osc2Position(send_value),
+            OnDragBehavior::Osc2Phase => AudioMessage::Osc2Phase(send_value),
 
             // Lfo1
-            OnDragBehavior::Lfo1Freq => audio_channel.send(AudioMessage::Lfo1Freq(send_value)),
+            OnDragBehavior::Lfo1Freq => AudioMessage::Lfo1Freq(send_value),
             OnDragBehavior::Lfo1Shape => {
                 let shape = match (send_value * 4.0) as usize {
                     0 => WaveShape::Sine,
@@ -174,10 +226,11 @@ fn on_drag_behavior(audio_channel: &mut mpsc::Sender<AudioMessage>, value: &mut
                     2 => WaveShape::Square,
                     _ => WaveShape::Saw,
                 };
-                audio_channel.send(AudioMessage::Lfo1Shape(shape))
+                AudioMessage::Lfo1Shape(shape)
             },
             // Lfo2
-            OnDragBehavior::Lfo2Freq => audio_channel.send(AudioMessage::Lfo2Freq(send_value)),
+            OnDragBehavior::Lfo2Freq => AudioMessage::Lfo2Freq(send_value),
+            OnDragBehavior::NoiseLevel => AudioMessage::NoiseLevel(send_value),
             OnDragBehavior::Lfo2Shape => {
                 let shape = match (send_value * 4.0) as usize {
                     0 => WaveShape::Sine,
@@ -185,48 +238,59 @@ fn on_drag_behavior(audio_channel: &mut mpsc::Sender<AudioMessage>, value: &mut
                     2 => WaveShape::Square,
                     _ => WaveShape::Saw,
                 };
-                audio_channel.send(AudioMessage::Lfo2Shape(shape))
+                AudioMessage::Lfo2Shape(shape)
             },
 
             // Filter1
-            OnDragBehavior::Filter1Freq => audio_channel.send(AudioMessage::Filter1Freq(send_value)),
+            OnDragBehavior::Filter1Freq => AudioMessage::Filter1Freq(send_value),
+            OnDragBehavior::Filter1Resonance => AudioMessage::Filter1Resonance(send_value),
+            OnDragBehavior::Filter1Keytrack => AudioMessage::Filter1Keytrack(exact_value(new_frame as usize, animation_frames)),
+            OnDragBehavior::Filter1CutoffAmount => AudioMessage::Filter1CutoffAmount(exact_value(new_frame as usize, animation_frames)),
             // Filter2
-            OnDragBehavior::Filter2Freq => audio_channel.send(AudioMessage::Filter2Freq(send_value)),
+            OnDragBehavior::Filter2Freq => AudioMessage::Filter2Freq(send_value),
+            OnDragBehavior::Filter2Resonance => AudioMessage::Filter2Resonance(send_value),
+            OnDragBehavior::Filter2Keytrack => AudioMessage::Filter2Keytrack(exact_value(new_frame as usize, animation_frames)),
+            OnDragBehavior::Filter2CutoffAmount => AudioMessage::Filter2CutoffAmount(exact_value(new_frame as usize, animation_frames)),
+            // Ladder
+            OnDragBehavior::LadderFreq => AudioMessage::LadderFreq(send_value),
+            OnDragBehavior::LadderResonance => AudioMessage::LadderResonance(send_value),
+            OnDragBehavior::LadderDrive => AudioMessage::LadderDrive(send_value),
 
             // Env1
-            OnDragBehavior::Env1Attack => audio_channel.send(AudioMessage::Env1Attack(send_value)),
-            OnDragBehavior::Env1Decay => audio_channel.send(AudioMessage::Env1Decay(send_value)),
-            OnDragBehavior::Env1Sustain => audio_channel.send(AudioMessage::Env1Sustain(send_value)),
-            OnDragBehavior::Env1Release => audio_channel.send(AudioMessage::Env1Release(send_value)),
+            OnDragBehavior::Env1Attack => AudioMessage::Env1Attack(send_value),
+            OnDragBehavior::Env1Decay => AudioMessage::Env1Decay(send_value),
+            OnDragBehavior::Env1Sustain => AudioMessage::Env1Sustain(send_value),
+            OnDragBehavior::Env1Release => AudioMessage::Env1Release(send_value),
             // Env2
-            OnDragBehavior::Env2Attack => audio_channel.send(AudioMessage::Env2Attack(send_value)),
-            OnDragBehavior::Env2Decay => audio_channel.send(AudioMessage::Env2Decay(send_value)),
-            OnDragBehavior::Env2Sustain => audio_channel.send(AudioMessage::Env2Sustain(send_value)),
-            OnDragBehavior::Env2Release => audio_channel.send(AudioMessage::Env2Release(send_value)),
+            OnDragBehavior::Env2Attack => AudioMessage::Env2Attack(send_value),
+            OnDragBehavior::Env2Decay => AudioMessage::Env2Decay(send_value),
+            OnDragBehavior::Env2Sustain => AudioMessage::Env2Sustain(send_value),
+            OnDragBehavior::Env2Release => AudioMessage::Env2Release(send_value),
             // Env3
-            OnDragBehavior::Env3Attack => audio_channel.send(AudioMessage::Env3Attack(send_value)),
-            OnDragBehavior::Env3Decay => audio_channel.send(AudioMessage::Env3Decay(send_value)),
-            OnDragBehavior::Env3Sustain => audio_channel.send(AudioMessage::Env3Sustain(send_value)),
-            OnDragBehavior::Env3Release => audio_channel.send(AudioMessage::Env3Release(send_value)),
+            OnDragBehavior::Env3Attack => AudioMessage::Env3Attack(send_value),
+            OnDragBehavior::Env3Decay => AudioMessage::Env3Decay(send_value),
+            OnDragBehavior::Env3Sustain => AudioMessage::Env3Sustain(send_value),
+            OnDragBehavior::Env3Release => AudioMessage::Env3Release(send_value),
 
             // Effects
             // Distortion
-            OnDragBehavior::EffectDistDrive => audio_channel.send(AudioMessage::DistDrive(send_value)),
-            OnDragBehavior::EffectDistWet => audio_channel.send(AudioMessage::DistWet(send_value)),
+            OnDragBehavior::EffectDistDrive => AudioMessage::DistDrive(send_value),
+            OnDragBehavior::EffectDistWet => AudioMessage::DistWet(send_value),
             // Delay
-            OnDragBehavior::EffectDelayFeedback => audio_channel.send(AudioMessage::DelayFeedback(send_value)),
-            OnDragBehavior::EffectDelayTime => audio_channel.send(AudioMessage::DelayTime(send_value)),
-            OnDragBehavior::EffectDelayWet => audio_channel.send(AudioMessage::DelayWet(send_value)),
+            OnDragBehavior::EffectDelayFeedback => AudioMessage::DelayFeedback(send_value),
+            OnDragBehavior::EffectDelayTime => AudioMessage::DelayTime(send_value),
+            OnDragBehavior::EffectDelayWet => AudioMessage::DelayWet(send_value),
             // Reverb
-            OnDragBehavior::EffectReverbSpread => audio_channel.send(AudioMessage::ReverbSpread(send_value)),
-            OnDragBehavior::EffectReverbDamp => audio_channel.send(AudioMessage::ReverbDamp(send_value)),
-            OnDragBehavior::EffectReverbWet => audio_channel.send(AudioMessage::ReverbWet(send_value)),
-            OnDragBehavior::EffectReverbSpace => audio_channel.send(AudioMessage::ReverbSpace(send_value)),
+            OnDragBehavior::EffectReverbSpread => AudioMessage::ReverbSpread(send_value),
+            OnDragBehavior::EffectReverbDamp => AudioMessage::ReverbDamp(send_value),
+            OnDragBehavior::EffectReverbWet => AudioMessage::ReverbWet(send_value),
+            OnDragBehavior::EffectReverbSpace => AudioMessage::ReverbSpace(send_value),
 
             // Master
-            OnDragBehavior::MasterGain => audio_channel.send(AudioMessage::MasterGain(send_value)),
+            OnDragBehavior::MasterGain => AudioMessage::MasterGain(send_value),
         };
 
-        result.unwrap();
+        // A full queue keeps only the newest value of this knob, update_system resends it
+        *unsent = audio_channel.push(msg).err();
     }
 }
\ No newline at end of file
@@ -1,15 +1,32 @@
-use std::{cmp::Ordering, sync::{mpsc, Arc}};
+use std::{cmp::Ordering, path::Path, sync::Arc};
 
 use realfft::{num_complex::Complex, num_traits::Zero, ComplexToReal, RealFftPlanner, RealToComplex};
 use sdl3::{pixels::FColor, render::{Canvas, FRect}, video::Window};
 
-use crate::{audio::{AudioMessage, Wavetable, WAVETABLE_FRAME_LENGTH}, common::{point_in_frect, ComponentVec}};
+use crate::{audio::{AudioMessage, Wavetable, MAX_WAVETABLE_FRAMES, WAVETABLE_FRAME_LENGTH, WAVETABLE_VARIATION_COUNT}, common::{point_in_frect, ComponentVec, Producer}, wav};
 
-const MAX_DRAWABLE_COUNT: usize = 1;
+const MAX_DRAWABLE_COUNT: usize = 2;
+/// Only written to the file header, frames play back at whatever pitch the oscillator is at
+const WAVETABLE_FILE_SAMPLE_RATE: u32 = 48000;
+/// Pixels per harmonic in the harmonic view, the last one is left empty to keep the bars apart
+const HARMONIC_BAR_WIDTH: usize = 4;
+/// Marks exports that hold every band-limited variation, followed by how many there are per frame
+const VARIATIONS_TAG: &str = "cav-synth2 variations=";
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum OnReleaseBehavior {
     Osc2WavetableTimeDomain,
+    /// One bar per harmonic, bars below the center line play their sine upside down
+    Osc2WavetableHarmonics,
+}
+
+impl OnReleaseBehavior {
+    fn bar_width(&self) -> usize {
+        match self {
+            Self::Osc2WavetableTimeDomain => 1,
+            Self::Osc2WavetableHarmonics => HARMONIC_BAR_WIDTH,
+        }
+    }
 }
 
 pub struct Drawables {
@@ -17,8 +34,11 @@ pub struct Drawables {
     rect: ComponentVec<FRect, MAX_DRAWABLE_COUNT>,
     on_release: ComponentVec<OnReleaseBehavior, MAX_DRAWABLE_COUNT>,
     values: ComponentVec<Vec<FRect>, MAX_DRAWABLE_COUNT>,
+    visible: ComponentVec<bool, MAX_DRAWABLE_COUNT>,
     r2cfft: Arc<dyn RealToComplex<f32>>,
     c2rfft: Arc<dyn ComplexToReal<f32>>,
+    /// Copy of the last wavetable sent to Osc2, what gets exported
+    current_wavetable: Box<Wavetable>,
 }
 
 impl Drawables {
@@ -28,12 +48,14 @@ impl Drawables {
             rect: ComponentVec::new(),
             on_release: ComponentVec::new(),
             values: ComponentVec::new(),
+            visible: ComponentVec::new(),
             r2cfft: fft_planner.plan_fft_forward(WAVETABLE_FRAME_LENGTH),
             c2rfft: fft_planner.plan_fft_inverse(WAVETABLE_FRAME_LENGTH),
+            current_wavetable: Box::new(Wavetable::new(1)),
         }
     }
 
-    pub fn spawn(&mut self, rect: FRect, on_release: OnReleaseBehavior) -> Result<(), ()> {
+    pub fn spawn(&mut self, rect: FRect, on_release: OnReleaseBehavior, visible: bool) -> Result<(), ()> {
         self.rect.push(rect)?;
         self.on_release.push(on_release)?;
         let center_line = rect.y + rect.h / 2.0;
@@ -41,96 +63,286 @@ impl Drawables {
             FRect::new(x as f32 + rect.x, center_line, 1.0, 0.0)
         });
         self.values.push(Vec::from_iter(iter))?;
+        self.visible.push(visible)?;
         Ok(())
     }
 }
 
 pub fn on_left_down_system(drawables: &mut Drawables, x: f32, y: f32) {
     for (i, rect) in drawables.rect.iter().enumerate() {
-        if point_in_frect(rect, x, y) {
+        if drawables.visible[i] && point_in_frect(rect, x, y) {
             let center_line = rect.y + rect.h / 2.0;
-            let (last_x, last_y) = update_value_point(&mut drawables.values[i], *rect, center_line, x, y);
+            let bar_width = drawables.on_release[i].bar_width();
+            let (last_x, last_y) = update_value_point(&mut drawables.values[i], *rect, bar_width, center_line, x, y);
+            snap_bars(&mut drawables.values[i], bar_width);
             drawables.active_drawable = Some((i, center_line, *rect, last_x, last_y));
             break;
         }
     }
 }
 
-pub fn on_left_release_system(audio_channel: &mut mpsc::Sender<AudioMessage>, drawables: &mut Drawables) {
+pub fn on_left_release_system(audio_channel: &mut Producer<AudioMessage>, drawables: &mut Drawables) -> Result<(), String> {
     if let Some((i, _, rect, _, _)) = drawables.active_drawable {
         let on_release = drawables.on_release[i];
-        let values = &drawables.values[i];
-        on_release_behavior(&drawables.r2cfft, &drawables.c2rfft, audio_channel, on_release, values, rect.h);
+        let frame_samples = on_release_behavior(&drawables.c2rfft, on_release, &drawables.values[i], rect.h);
+
+        let mut new_wavetable = Box::new(Wavetable::new(1));
+        build_variations(&drawables.r2cfft, &drawables.c2rfft, &mut frame_samples.clone(), &mut new_wavetable, 0);
+        // The other views follow what was drawn, the one drawn in stays as it is
+        for other in 0..drawables.values.len() {
+            if drawables.on_release[other] != on_release {
+                draw_view(drawables, other, &frame_samples);
+            }
+        }
         drawables.active_drawable = None;
+        return send_wavetable(audio_channel, &mut drawables.current_wavetable, new_wavetable);
     }
+    Ok(())
 }
 
 pub fn on_mouse_move_system(drawables: &mut Drawables, x: f32, y: f32) {
     if let Some((i, center_line, rect, last_x, last_y)) = drawables.active_drawable {
-        let (last_x, last_y) = update_value_interp(&mut drawables.values[i], rect, last_x, last_y, center_line, x, y);
+        let bar_width = drawables.on_release[i].bar_width();
+        let (last_x, last_y) = update_value_interp(&mut drawables.values[i], rect, bar_width, last_x, last_y, center_line, x, y);
+        snap_bars(&mut drawables.values[i], bar_width);
         drawables.active_drawable = Some((i, center_line, rect, last_x, last_y));
     }
 }
+/// Loads a single-cycle or multi-frame wavetable file into Osc2 and draws its first frame
+pub fn load_wavetable_system(audio_channel: &mut Producer<AudioMessage>, drawables: &mut Drawables, path: &Path) -> Result<(), String> {
+    let wav = wav::read(path)?;
+    let channels = wav.channels as usize;
+    let mono: Vec<f32> = wav.samples.chunks_exact(channels)
+        .map(|sample| sample.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    // Without a clm chunk, anything that does not split into whole frames is taken as one cycle
+    let frame_length = match wav.cycle_length {
+        Some(cycle_length) => cycle_length,
+        None if mono.len() % WAVETABLE_FRAME_LENGTH == 0 => WAVETABLE_FRAME_LENGTH,
+        None => mono.len(),
+    };
+    // Our own exports store every variation after the frame they come from
+    let stored_variations = wav.clm.as_deref()
+        .and_then(|clm| clm.split_once(VARIATIONS_TAG))
+        .and_then(|(_, count)| count.trim().parse().ok())
+        .filter(|&count: &usize| count > 0)
+        .unwrap_or(1);
+    let frame_stride = frame_length * stored_variations;
+    let frame_count = (mono.len() / frame_stride.max(1)).min(MAX_WAVETABLE_FRAMES);
+    if frame_count == 0 {
+        return Err(format!("'{}' is shorter than one {frame_length} sample frame", path.display()));
+    }
+
+    let mut new_wavetable = Box::new(Wavetable::new(frame_count));
+    let mut fft_planner = RealFftPlanner::new();
+    let mut first_frame = [0.0; WAVETABLE_FRAME_LENGTH];
+    for (frame, samples) in mono.chunks_exact(frame_stride).take(frame_count).enumerate() {
+        if stored_variations == WAVETABLE_VARIATION_COUNT && frame_length == WAVETABLE_FRAME_LENGTH {
+            for (variation, variation_samples) in samples.chunks_exact(frame_length).enumerate() {
+                new_wavetable.variation_mut(frame, variation).copy_from_slice(variation_samples);
+            }
+            if frame == 0 {
+                first_frame.copy_from_slice(&samples[..frame_length]);
+            }
+            continue;
+        }
+
+        // Anything else is rebuilt from the full band frame
+        let mut frame_samples = resample_frame(&mut fft_planner, &samples[..frame_length]);
+        if frame == 0 {
+            first_frame = frame_samples;
+        }
+        build_variations(&drawables.r2cfft, &drawables.c2rfft, &mut frame_samples, &mut new_wavetable, frame);
+    }
+
+    send_wavetable(audio_channel, &mut drawables.current_wavetable, new_wavetable)?;
+    for i in 0..drawables.values.len() {
+        draw_view(drawables, i, &first_frame);
+    }
+    Ok(())
+}
+
+/// Writes Osc2's wavetable, either the full band frames other editors read or every variation for an exact reload
+pub fn export_wavetable_system(drawables: &Drawables, path: &Path, all_variations: bool) -> Result<(), String> {
+    let wavetable = &drawables.current_wavetable;
+    let variation_count = if all_variations { WAVETABLE_VARIATION_COUNT } else { 1 };
+    let mut samples = Vec::with_capacity(wavetable.frame_count() * variation_count * WAVETABLE_FRAME_LENGTH);
+    for frame in 0..wavetable.frame_count() {
+        for variation in 0..variation_count {
+            samples.extend_from_slice(wavetable.variation(frame, variation));
+        }
+    }
+
+    let clm = if all_variations {
+        format!("<!>{WAVETABLE_FRAME_LENGTH} 00000000 {VARIATIONS_TAG}{WAVETABLE_VARIATION_COUNT}")
+    } else {
+        format!("<!>{WAVETABLE_FRAME_LENGTH} 00000000 cav-synth2")
+    };
+    wav::write_wavetable(path, WAVETABLE_FILE_SAMPLE_RATE, &clm, &samples)
+}
+
+/// Shows the drawables with `on_release` and hides the rest
+pub fn show_system(drawables: &mut Drawables, on_release: OnReleaseBehavior) {
+    for (visible, shown) in drawables.visible.iter_mut().zip(drawables.on_release.iter()) {
+        *visible = *shown == on_release;
+    }
+    drawables.active_drawable = None;
+}
+
 pub fn render_system(canvas: &mut Canvas<Window>, drawables: &Drawables) -> Result<(), sdl3::Error> {
     canvas.set_draw_color(FColor::RGBA(0.0, 1.0, 1.0, 0.6));
-    for values in drawables.values.iter() {
-        canvas.draw_rects(&values)?;
+    for (values, visible) in drawables.values.iter().zip(drawables.visible.iter()) {
+        if *visible {
+            canvas.draw_rects(&values)?;
+        }
     }
     Ok(())
 }
 
 
+/// Full band frame of what was drawn
 fn on_release_behavior(
-    r2c: &Arc<dyn RealToComplex<f32>>,
     c2r: &Arc<dyn ComplexToReal<f32>>,
-    audio_channel: &mut mpsc::Sender<AudioMessage>,
     on_release: OnReleaseBehavior,
-    values: &Vec<FRect>,
+    values: &[FRect],
     height: f32
-) {
+) -> [f32; WAVETABLE_FRAME_LENGTH] {
     match on_release {
         OnReleaseBehavior::Osc2WavetableTimeDomain => {
-            const PARTIAL_COUNT: usize = WAVETABLE_FRAME_LENGTH / 2 + 1;
-
-            let mut default_variation: [f32; WAVETABLE_FRAME_LENGTH] = std::array::from_fn(|i| {
+            std::array::from_fn(|i| {
                 let index1 = i / 8;
                 let index2 = ((i + 1) / 8) % 256;
                 let ratio = (i % 8) as f32 / 8.0;
                 2.0 * (values[index1].h + (values[index2].h - values[index1].h) * ratio) / height
-            });
-
-            let mut new_wavetable: Box<Wavetable> = Box::new([0.0; WAVETABLE_FRAME_LENGTH * 8]);
-
-            let mut freq_domain = [Complex::zero(); PARTIAL_COUNT];
-            r2c.process(&mut default_variation, &mut freq_domain).unwrap();
-
-            freq_domain[0] = Complex::zero();
-            c2r.process(&mut freq_domain.clone(), &mut new_wavetable[0..WAVETABLE_FRAME_LENGTH]).unwrap();
-            freq_domain[PARTIAL_COUNT / 2..].fill(Complex::zero());
-            c2r.process(&mut freq_domain.clone(), &mut new_wavetable[WAVETABLE_FRAME_LENGTH..WAVETABLE_FRAME_LENGTH*2]).unwrap();
-            freq_domain[PARTIAL_COUNT / 4 .. PARTIAL_COUNT / 2].fill(Complex::zero());
-            c2r.process(&mut freq_domain.clone(), &mut new_wavetable[WAVETABLE_FRAME_LENGTH*2..WAVETABLE_FRAME_LENGTH*3]).unwrap();
-            freq_domain[PARTIAL_COUNT / 8 .. PARTIAL_COUNT / 4].fill(Complex::zero());
-            c2r.process(&mut freq_domain.clone(), &mut new_wavetable[WAVETABLE_FRAME_LENGTH*3..WAVETABLE_FRAME_LENGTH*4]).unwrap();
-            freq_domain[PARTIAL_COUNT / 16 .. PARTIAL_COUNT / 8].fill(Complex::zero());
-            c2r.process(&mut freq_domain.clone(), &mut new_wavetable[WAVETABLE_FRAME_LENGTH*4..WAVETABLE_FRAME_LENGTH*5]).unwrap();
-            freq_domain[PARTIAL_COUNT / 32 .. PARTIAL_COUNT / 16].fill(Complex::zero());
-            c2r.process(&mut freq_domain.clone(), &mut new_wavetable[WAVETABLE_FRAME_LENGTH*5..WAVETABLE_FRAME_LENGTH*6]).unwrap();
-            freq_domain[PARTIAL_COUNT / 64 .. PARTIAL_COUNT / 32].fill(Complex::zero());
-            c2r.process(&mut freq_domain.clone(), &mut new_wavetable[WAVETABLE_FRAME_LENGTH*6..WAVETABLE_FRAME_LENGTH*7]).unwrap();
-            freq_domain[PARTIAL_COUNT / 128 .. PARTIAL_COUNT / 64].fill(Complex::zero());
-            c2r.process(&mut freq_domain.clone(), &mut new_wavetable[WAVETABLE_FRAME_LENGTH*7..]).unwrap();
-
-            let max = new_wavetable.into_iter().reduce(f32::max).unwrap_or(0.0).abs();
-            new_wavetable.iter_mut().for_each(|x| *x /= max);
-            
-            audio_channel.send(AudioMessage::Osc2WavetableUpdate(new_wavetable)).unwrap();
+            })
+        },
+        OnReleaseBehavior::Osc2WavetableHarmonics => {
+            // Bars are drawn upwards, a sine's bin is negative imaginary
+            let mut freq_domain = [Complex::zero(); WAVETABLE_FRAME_LENGTH / 2 + 1];
+            for (harmonic, bar) in values.chunks(HARMONIC_BAR_WIDTH).enumerate() {
+                freq_domain[harmonic + 1] = Complex::new(0.0, 2.0 * bar[0].h / height);
+            }
+            let mut frame_samples = [0.0; WAVETABLE_FRAME_LENGTH];
+            c2r.process(&mut freq_domain, &mut frame_samples).unwrap();
+            frame_samples
         },
     }
 }
 
-fn update_value_interp(values: &mut Vec<FRect>, rect: FRect, last_x: usize, last_height: f32, center_line: f32, x: f32, y: f32) -> (usize, f32) {
-    let (changed_index, new_height) = update_value_point(values, rect, center_line, x, y);
+/// Redraws drawable `i` to show a frame
+fn draw_view(drawables: &mut Drawables, i: usize, frame_samples: &[f32; WAVETABLE_FRAME_LENGTH]) {
+    let rect = drawables.rect[i];
+    match drawables.on_release[i] {
+        OnReleaseBehavior::Osc2WavetableTimeDomain => draw_frame(&mut drawables.values[i], rect, frame_samples),
+        OnReleaseBehavior::Osc2WavetableHarmonics => draw_harmonics(&drawables.r2cfft, &mut drawables.values[i], rect, frame_samples),
+    }
+}
+
+/// Normalizes the wavetable and queues it for Osc2, `current_wavetable` only changes once it is queued
+fn send_wavetable(audio_channel: &mut Producer<AudioMessage>, current_wavetable: &mut Box<Wavetable>, mut wavetable: Box<Wavetable>) -> Result<(), String> {
+    let samples = wavetable.samples_mut();
+    let max = samples.iter().fold(0.0, |max: f32, x| max.max(x.abs()));
+    if max != 0.0 {
+        samples.iter_mut().for_each(|x| *x /= max);
+    }
+
+    audio_channel.push(AudioMessage::Osc2WavetableUpdate(wavetable.clone()))
+        .map_err(|_| String::from("Audio queue is full, the wavetable was not sent"))?;
+    *current_wavetable = wavetable;
+    Ok(())
+}
+
+/// Stretches one cycle of any length to `WAVETABLE_FRAME_LENGTH` through its spectrum, so shrinking it cannot alias
+fn resample_frame(fft_planner: &mut RealFftPlanner<f32>, samples: &[f32]) -> [f32; WAVETABLE_FRAME_LENGTH] {
+    let mut resampled = [0.0; WAVETABLE_FRAME_LENGTH];
+    if samples.len() == WAVETABLE_FRAME_LENGTH {
+        resampled.copy_from_slice(samples);
+        return resampled;
+    }
+
+    let r2c = fft_planner.plan_fft_forward(samples.len());
+    let mut spectrum = r2c.make_output_vec();
+    r2c.process(&mut samples.to_vec(), &mut spectrum).unwrap();
+
+    let mut freq_domain = [Complex::zero(); WAVETABLE_FRAME_LENGTH / 2 + 1];
+    let partials = spectrum.len().min(freq_domain.len());
+    freq_domain[..partials].copy_from_slice(&spectrum[..partials]);
+    // The inverse transform wants both ends purely real
+    freq_domain[0].im = 0.0;
+    freq_domain[WAVETABLE_FRAME_LENGTH / 2].im = 0.0;
+    let scale = 1.0 / samples.len() as f32;
+    freq_domain.iter_mut().for_each(|x| *x *= scale);
+
+    fft_planner.plan_fft_inverse(WAVETABLE_FRAME_LENGTH).process(&mut freq_domain, &mut resampled).unwrap();
+    resampled
+}
+
+/// Sets the outline to a frame, scaled so its loudest sample reaches the edge
+fn draw_frame(values: &mut [FRect], rect: FRect, frame_samples: &[f32; WAVETABLE_FRAME_LENGTH]) {
+    let max = frame_samples.iter().fold(0.0, |max: f32, x| max.max(x.abs()));
+    if max == 0.0 {
+        values.iter_mut().for_each(|value| value.set_h(0.0));
+        return;
+    }
+    let step = WAVETABLE_FRAME_LENGTH / values.len();
+    for (x, value) in values.iter_mut().enumerate() {
+        value.set_h(frame_samples[x * step] / max * rect.h / 2.0);
+    }
+}
+
+/// Sets the bars to a frame's harmonics, scaled so the loudest reaches the edge
+///
+/// Only the sine part of each harmonic has a sign to show, the bar takes its sign and the full amplitude.
+fn draw_harmonics(r2c: &Arc<dyn RealToComplex<f32>>, values: &mut [FRect], rect: FRect, frame_samples: &[f32; WAVETABLE_FRAME_LENGTH]) {
+    let mut freq_domain = [Complex::zero(); WAVETABLE_FRAME_LENGTH / 2 + 1];
+    r2c.process(&mut frame_samples.clone(), &mut freq_domain).unwrap();
+
+    let bar_count = values.len() / HARMONIC_BAR_WIDTH;
+    let harmonics = &freq_domain[1..=bar_count];
+    let max = harmonics.iter().fold(0.0, |max: f32, x| max.max(x.norm()));
+    for (bar, harmonic) in values.chunks_mut(HARMONIC_BAR_WIDTH).zip(harmonics) {
+        let amplitude = if max == 0.0 { 0.0 } else { harmonic.norm() / max };
+        bar[0].set_h(-amplitude.copysign(-harmonic.im) * rect.h / 2.0);
+    }
+    snap_bars(values, HARMONIC_BAR_WIDTH);
+}
+
+/// Gives every pixel of a bar the height of its first one
+fn snap_bars(values: &mut [FRect], bar_width: usize) {
+    if bar_width == 1 {
+        return;
+    }
+    for bar in values.chunks_mut(bar_width) {
+        let height = bar[0].h;
+        let (last, rest) = bar.split_last_mut().unwrap();
+        rest.iter_mut().for_each(|value| value.set_h(height));
+        last.set_h(0.0);
+    }
+}
+
+/// Fills every band-limited variation of one frame, each keeps half the partials of the one before
+fn build_variations(
+    r2c: &Arc<dyn RealToComplex<f32>>,
+    c2r: &Arc<dyn ComplexToReal<f32>>,
+    frame_samples: &mut [f32],
+    wavetable: &mut Wavetable,
+    frame: usize,
+) {
+    const PARTIAL_COUNT: usize = WAVETABLE_FRAME_LENGTH / 2 + 1;
+
+    let mut freq_domain = [Complex::zero(); PARTIAL_COUNT];
+    r2c.process(frame_samples, &mut freq_domain).unwrap();
+
+    freq_domain[0] = Complex::zero();
+    for variation in 0..WAVETABLE_VARIATION_COUNT {
+        freq_domain[PARTIAL_COUNT >> variation..].fill(Complex::zero());
+        c2r.process(&mut freq_domain.clone(), wavetable.variation_mut(frame, variation)).unwrap();
+    }
+}
+
+fn update_value_interp(values: &mut Vec<FRect>, rect: FRect, bar_width: usize, last_x: usize, last_height: f32, center_line: f32, x: f32, y: f32) -> (usize, f32) {
+    let (changed_index, new_height) = update_value_point(values, rect, bar_width, center_line, x, y);
     
     let (b, slope, min, max) = match last_x.cmp(&changed_index) {
         Ordering::Less => {
@@ -152,8 +364,8 @@ fn update_value_interp(values: &mut Vec<FRect>, rect: FRect, last_x: usize, last
     (changed_index, new_height)
 }
 
-fn update_value_point(values: &mut Vec<FRect>, rect: FRect, center_line: f32, x: f32, y: f32) -> (usize, f32) {
-    let changed_index = ((x - rect.x) as usize).clamp(0, rect.w.abs() as usize - 1);
+fn update_value_point(values: &mut Vec<FRect>, rect: FRect, bar_width: usize, center_line: f32, x: f32, y: f32) -> (usize, f32) {
+    let changed_index = ((x - rect.x) as usize).clamp(0, rect.w.abs() as usize - 1) / bar_width * bar_width;
     let new_height = (y - center_line).clamp(-rect.h / 2.0, rect.h / 2.0);
     values[changed_index].set_h(new_height);
     (changed_index, new_height)
@@ -1,8 +1,8 @@
-use std::{collections::HashSet, sync::mpsc};
+use std::collections::{HashSet, VecDeque};
 
 use sdl3::{render::{Canvas, FPoint, FRect, Texture}, video::Window};
 
-use crate::{audio::{AudioMessage, InputJack, OutputJack}, common::{frect_center, point_in_frect, ComponentVec}, gui::{animation::Animation, cable::{self, Cable, Cables}}, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::{audio::{AudioMessage, InputJack, OutputJack}, common::{frect_center, point_in_frect, ComponentVec, Producer}, gui::{animation::Animation, cable::{self, Cable, Cables}}, SCREEN_HEIGHT, SCREEN_WIDTH};
 
 const MAX_INPUT_JACKS: usize = 64;
 const MAX_OUTPUT_JACKS: usize = 64;
@@ -27,6 +27,8 @@ pub struct JackData {
     cable_combos: HashSet<(InputJack, OutputJack)>,
     right_clicked_cable: Option<(usize, FRect)>,
     cable_slider_animation: Animation,
+    /// Cable edits the full audio queue turned away, in order, the audio thread addresses cables by index
+    unsent: VecDeque<AudioMessage>,
 }
 
 impl JackData {
@@ -40,6 +42,7 @@ impl JackData {
             right_clicked_cable: None,
             cable_slider_animation: super::SLIDER_CABLE_ANIMATION,
             cable_combos: HashSet::new(),
+            unsent: VecDeque::new(),
         }
     }
 
@@ -56,6 +59,25 @@ impl JackData {
     }
 }
 
+/// Sends a cable edit, or queues it behind earlier ones so the audio thread's cables keep matching ours
+fn send(audio_channel: &mut Producer<AudioMessage>, jack_data: &mut JackData, msg: AudioMessage) {
+    if !jack_data.unsent.is_empty() {
+        jack_data.unsent.push_back(msg);
+    } else if let Err(msg) = audio_channel.push(msg) {
+        jack_data.unsent.push_back(msg);
+    }
+}
+
+/// Retries the queued cable edits, called once per frame
+pub fn update_system(audio_channel: &mut Producer<AudioMessage>, jack_data: &mut JackData) {
+    while let Some(msg) = jack_data.unsent.pop_front() {
+        if let Err(msg) = audio_channel.push(msg) {
+            jack_data.unsent.push_front(msg);
+            break;
+        }
+    }
+}
+
 pub fn render_system(canvas: &mut Canvas<Window>, textures: &[Texture], jack_data: &JackData, mouse_pos: FPoint) -> Result<(), sdl3::Error> {
     for &rect in jack_data.outputs.rect.iter() {
         canvas.copy(&textures[super::JACK_OUTPUT_TEXTURE], None, Some(rect))?;
@@ -93,7 +115,7 @@ pub fn on_left_down_system(jack_data: &mut JackData, x: f32, y: f32) {
     }
 }
 
-pub fn on_left_release_system(audio_channel: &mut mpsc::Sender<AudioMessage>, jack_data: &mut JackData, mouse_pos: FPoint) {
+pub fn on_left_release_system(audio_channel: &mut Producer<AudioMessage>, jack_data: &mut JackData, mouse_pos: FPoint) {
     if let Some((input_index, start)) = jack_data.clicked_input {
         for (output_index, end_rect) in jack_data.outputs.rect.iter().enumerate() {
             if point_in_frect(end_rect, mouse_pos.x, mouse_pos.y) {
@@ -101,7 +123,7 @@ pub fn on_left_release_system(audio_channel: &mut mpsc::Sender<AudioMessage>, ja
                 if !jack_data.cable_combos.contains(&combo) {
                     jack_data.cables.push(Cable::new(start, frect_center(end_rect), combo)).unwrap();
                     jack_data.cable_combos.insert(combo);
-                    audio_channel.send(AudioMessage::CableConnection(combo.0, combo.1)).unwrap();
+                    send(audio_channel, jack_data, AudioMessage::CableConnection(combo.0, combo.1));
                 }
                 break;
             }
@@ -114,7 +136,7 @@ pub fn on_left_release_system(audio_channel: &mut mpsc::Sender<AudioMessage>, ja
                 if !jack_data.cable_combos.contains(&combo) {
                     jack_data.cables.push(Cable::new(start, frect_center(end_rect), combo)).unwrap();
                     jack_data.cable_combos.insert(combo);
-                    audio_channel.send(AudioMessage::CableConnection(combo.0, combo.1)).unwrap();
+                    send(audio_channel, jack_data, AudioMessage::CableConnection(combo.0, combo.1));
                 }
                 break;
             }
@@ -123,7 +145,7 @@ pub fn on_left_release_system(audio_channel: &mut mpsc::Sender<AudioMessage>, ja
     }
 }
 
-pub fn on_right_down_system(audio_channel: &mut mpsc::Sender<AudioMessage>, jack_data: &mut JackData, x: f32, y: f32, clicks: u8) {
+pub fn on_right_down_system(audio_channel: &mut Producer<AudioMessage>, jack_data: &mut JackData, x: f32, y: f32, clicks: u8) {
     let mouse_pos = FPoint::new(x, y);
     let maybe_remove_index = jack_data.cables.iter().rev().position(|cable| cable.is_touching(mouse_pos));
 
@@ -148,7 +170,7 @@ pub fn on_right_down_system(audio_channel: &mut mpsc::Sender<AudioMessage>, jack
             let cable_combo = jack_data.cables[cable_index].combo();
             jack_data.cable_combos.remove(&cable_combo);
             jack_data.cables.remove(cable_index);
-            audio_channel.send(AudioMessage::CableRemove(cable_index)).unwrap();
+            send(audio_channel, jack_data, AudioMessage::CableRemove(cable_index));
         },
         _ => {},
     }
@@ -158,7 +180,7 @@ pub fn on_right_release_system(jack_data: &mut JackData) {
     jack_data.right_clicked_cable = None;
 }
 
-pub fn on_mouse_move_system(audio_channel: &mut mpsc::Sender<AudioMessage>, jack_data: &mut JackData, _xrel: f32, yrel: f32) {
+pub fn on_mouse_move_system(audio_channel: &mut Producer<AudioMessage>, jack_data: &mut JackData, _xrel: f32, yrel: f32) {
     if let Some((cable_index, _)) = jack_data.right_clicked_cable {
         let drag_amt = -yrel/200.0;
         let cable = &mut jack_data.cables[cable_index];
@@ -170,7 +192,7 @@ pub fn on_mouse_move_system(audio_channel: &mut mpsc::Sender<AudioMessage>, jack
 
         cable.set_value(new_value);
         if old_frame != new_frame as usize {
-            audio_channel.send(AudioMessage::CableAttenuation(cable_index, new_value)).unwrap();
+            send(audio_channel, jack_data, AudioMessage::CableAttenuation(cable_index, new_value));
         }
     }
 }
\ No newline at end of file
@@ -1,14 +1,20 @@
-use std::sync::mpsc;
-
 use sdl3::{render::{Canvas, FRect, Texture}, video::Window};
 
-use crate::{audio::AudioMessage, common::{point_in_frect, ComponentVec}, gui::animation::Animation};
+use crate::{audio::{AudioMessage, FilterMode, Interpolation}, common::{point_in_frect, ComponentVec, Producer}, gui::animation::Animation};
 
 const MAX_TOGGLEABLE_COUNT: usize = 128;
 
 #[derive(Clone, Copy)]
 pub enum OnToggleBehavior {
     None,
+    Filter1Mode,
+    Filter2Mode,
+    Osc1ThroughZero,
+    Osc2ThroughZero,
+    NoiseShared,
+    Osc2Interpolation,
+    /// Handled by the GUI, switches Osc2's drawable between the time domain and harmonic views
+    Osc2WavetableView,
 }
 
 pub struct Toggleables {
@@ -16,6 +22,7 @@ pub struct Toggleables {
     on_left_click: ComponentVec<OnToggleBehavior, MAX_TOGGLEABLE_COUNT>,
     state: ComponentVec<usize, MAX_TOGGLEABLE_COUNT>,
     render: ComponentVec<Animation, MAX_TOGGLEABLE_COUNT>,
+    unsent: ComponentVec<Option<AudioMessage>, MAX_TOGGLEABLE_COUNT>,
 }
 
 impl Toggleables {
@@ -25,6 +32,7 @@ impl Toggleables {
             on_left_click: ComponentVec::new(),
             state: ComponentVec::new(),
             render: ComponentVec::new(),
+            unsent: ComponentVec::new(),
         }
     }
 
@@ -39,6 +47,7 @@ impl Toggleables {
         self.on_left_click.push(on_left_click)?;
         self.state.push(state)?;
         self.render.push(animation)?;
+        self.unsent.push(None)?;
         Ok(())
     }
 }
@@ -52,16 +61,55 @@ pub fn render_system(canvas: &mut Canvas<Window>, textures: &[Texture], toggleab
     Ok(())
 }
 
-pub fn on_left_down_system(audio_channel: &mut mpsc::Sender<AudioMessage>, toggleables: &mut Toggleables, x: f32, y: f32, clicks: u8) {
+/// Returns the clicked toggle and its new state, for the behaviors that do not reach the audio thread
+pub fn on_left_down_system(audio_channel: &mut Producer<AudioMessage>, toggleables: &mut Toggleables, x: f32, y: f32, clicks: u8) -> Option<(OnToggleBehavior, usize)> {
     for (i, rect) in toggleables.rect.iter().enumerate() {
         if point_in_frect(&rect, x, y) {
             let on_click = toggleables.on_left_click[i];
             let state = &mut toggleables.state[i];
             *state = (*state + 1) % toggleables.render[i].get_frame_count();
-            match on_click {
-                OnToggleBehavior::None => {},
+            let msg = match on_click {
+                OnToggleBehavior::None | OnToggleBehavior::Osc2WavetableView => None,
+                OnToggleBehavior::Filter1Mode => Some(AudioMessage::Filter1Mode(filter_mode(*state))),
+                OnToggleBehavior::Filter2Mode => Some(AudioMessage::Filter2Mode(filter_mode(*state))),
+                OnToggleBehavior::Osc1ThroughZero => Some(AudioMessage::Osc1ThroughZero(*state != 0)),
+                OnToggleBehavior::Osc2ThroughZero => Some(AudioMessage::Osc2ThroughZero(*state != 0)),
+                OnToggleBehavior::NoiseShared => Some(AudioMessage::NoiseShared(*state != 0)),
+                OnToggleBehavior::Osc2Interpolation => Some(AudioMessage::Osc2Interpolation(interpolation(*state))),
+            };
+            // A full queue keeps only the newest state of this toggle, update_system resends it
+            if let Some(msg) = msg {
+                toggleables.unsent[i] = audio_channel.push(msg).err();
             }
-            break;
+            return Some((on_click, *state));
+        }
+    }
+    None
+}
+
+/// Resends the toggle states a full queue turned away, called once per frame
+pub fn update_system(audio_channel: &mut Producer<AudioMessage>, toggleables: &mut Toggleables) {
+    for unsent in toggleables.unsent.iter_mut() {
+        if let Some(msg) = unsent.take() {
+            *unsent = audio_channel.push(msg).err();
         }
     }
-}
\ No newline at end of file
+}
+
+fn filter_mode(state: usize) -> FilterMode {
+    match state {
+        0 => FilterMode::Lowpass,
+        1 => FilterMode::Highpass,
+        2 => FilterMode::Bandpass,
+        3 => FilterMode::Notch,
+        _ => FilterMode::Peak,
+    }
+}
+
+fn interpolation(state: usize) -> Interpolation {
+    match state {
+        0 => Interpolation::Linear,
+        1 => Interpolation::Hermite,
+        _ => Interpolation::Sinc,
+    }
+}
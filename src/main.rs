@@ -4,6 +4,7 @@ mod audio;
 mod gui;
 mod synth;
 mod common;
+mod wav;
 
 const FRAME_RATE: usize = 60;
 const SCREEN_WIDTH: u32 = 1260;
@@ -11,6 +12,23 @@ const SCREEN_HEIGHT: u32 = 800;
 
 fn main() {
     //unsafe { std::env::set_var("RUST_BACKTRACE", "1") };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "render") {
+        if let Err(err) = audio::offline::run_cli(&args[1..]) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let audio_settings = match audio::device::run_cli(&args) {
+        Ok(Some(settings)) => settings,
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        },
+    };
+
     let sdl3_context = sdl3::init().expect("Failed to initialize sdl3");
     let video_subsystem = sdl3_context.video().expect("Failed to initialize video subsystem");
     
@@ -21,7 +39,7 @@ fn main() {
     let event_pump = sdl3_context.event_pump().expect("Failed to initialize event pump");
     let texture_creator = canvas.texture_creator();
 
-    let mut synth = synth::Synth::init(canvas, event_pump, & texture_creator);
+    let mut synth = synth::Synth::init(canvas, event_pump, & texture_creator, &audio_settings);
 
     while !synth.should_quit() {
         let start = std::time::Instant::now();
@@ -1,18 +1,26 @@
 mod midi;
 
-use std::sync::mpsc;
 use midir::MidiInputConnection;
 use sdl3::{event::Event, mouse::MouseButton, render::{Canvas, TextureCreator}, video::{Window, WindowContext}, Error, EventPump};
+use crate::audio::device::AudioSettings;
+use crate::audio::module::ModuleSlot;
+use crate::audio::Wavetable;
+use crate::common::{ring_buffer, Consumer};
 use crate::gui::Gui;
 
+const AUDIO_QUEUE_CAPACITY: usize = 1024;
+const SYNTH_QUEUE_CAPACITY: usize = 256;
+
 pub enum SynthMessage {
     MasterMeter(f32, f32), // Need to be sqrted on use
+    DropWavetable(Box<Wavetable>), // Freed here instead of on the audio thread
+    DropModule(ModuleSlot), // Freed here instead of on the audio thread
 }
 
 pub struct Synth<'a> {
     canvas: Canvas<Window>,
     event_pump: EventPump,
-    audio_events: mpsc::Receiver<SynthMessage>,
+    audio_events: Consumer<SynthMessage>,
 
     gui: Gui<'a>,
     _midi_connection: Option<MidiInputConnection<()>>,
@@ -22,11 +30,12 @@ pub struct Synth<'a> {
 }
 
 impl <'a> Synth<'a> {
-    pub fn init(canvas: Canvas<Window>, event_pump: EventPump, texture_creator: &'a TextureCreator<WindowContext>) -> Self {
-        let (audio_sender, audio_receiver) = mpsc::channel();
-        let (synth_sender, audio_events) = mpsc::channel();
-        let _stream = crate::audio::init(audio_receiver, synth_sender).expect("Failed to initialize audio thread");
-        let _midi_connection = midi::setup_midi(audio_sender.clone())
+    pub fn init(canvas: Canvas<Window>, event_pump: EventPump, texture_creator: &'a TextureCreator<WindowContext>, audio_settings: &AudioSettings) -> Self {
+        let (audio_sender, audio_receiver) = ring_buffer(AUDIO_QUEUE_CAPACITY);
+        let (midi_sender, midi_receiver) = ring_buffer(AUDIO_QUEUE_CAPACITY);
+        let (synth_sender, audio_events) = ring_buffer(SYNTH_QUEUE_CAPACITY);
+        let _stream = crate::audio::init(vec![audio_receiver, midi_receiver], synth_sender, audio_settings).expect("Failed to initialize audio thread");
+        let _midi_connection = midi::setup_midi(midi_sender)
             .map_err(|err| eprintln!("{}", err))
             .ok();
         let gui = Gui::new(audio_sender, texture_creator);
@@ -49,6 +58,8 @@ impl <'a> Synth<'a> {
             match event {
                 Event::Quit { .. } => self.should_quit = true,
                 Event::TextInput { text, .. } => self.gui.text_input(text),
+                Event::KeyDown { keycode: Some(keycode), keymod, repeat: false, .. } => self.gui.key_down(keycode, keymod),
+                Event::DropFile { filename, .. } => self.gui.drop_file(filename),
                 Event::MouseButtonDown { mouse_btn, clicks, x, y, .. } => {
                     match mouse_btn {
                         MouseButton::Left => self.gui.left_mouse_down(x, y, clicks),
@@ -67,9 +78,12 @@ impl <'a> Synth<'a> {
                 _ => {},
             }
         }
-        for msg in self.audio_events.try_iter() {
+        self.gui.update();
+        while let Some(msg) = self.audio_events.pop() {
             match msg {
                 SynthMessage::MasterMeter(left, right) => self.gui.master_meter(left, right),
+                SynthMessage::DropWavetable(wavetable) => drop(wavetable),
+                SynthMessage::DropModule(module) => drop(module),
             }
         }
     }
@@ -1,11 +1,11 @@
-use std::sync::mpsc::Sender;
 use crate::audio::AudioMessage;
+use crate::common::Producer;
 
 use midir::MidiInput;
 use midir::Ignore;
 use midir::MidiInputConnection;
 
-pub fn setup_midi(output: Sender<AudioMessage>) -> Result<MidiInputConnection<()>, String> {
+pub fn setup_midi(mut output: Producer<AudioMessage>) -> Result<MidiInputConnection<()>, String> {
     let mut midi_in = MidiInput::new("cav-synth").map_err(|err| err.to_string())?;
     midi_in.ignore(Ignore::TimeAndActiveSense);
 
@@ -23,9 +23,9 @@ pub fn setup_midi(output: Sender<AudioMessage>) -> Result<MidiInputConnection<()
             match message[0] {
                 144 => { // Key press / key release
                     if message[2] != 0 {
-                        output.send(AudioMessage::KeyPress(message[1], message[2])).unwrap();
+                        let _ = output.push(AudioMessage::KeyPress(message[1], message[2]));
                     } else {
-                        output.send(AudioMessage::KeyRelease(message[1])).unwrap();
+                        let _ = output.push(AudioMessage::KeyRelease(message[1]));
                     }
                 }
 
@@ -33,9 +33,9 @@ pub fn setup_midi(output: Sender<AudioMessage>) -> Result<MidiInputConnection<()
                     match message[1] {
                         64 => {
                             if message[2] == 0 {
-                                output.send(AudioMessage::PedalRelease).unwrap();
+                                let _ = output.push(AudioMessage::PedalRelease);
                             } else {
-                                output.send(AudioMessage::PedalPress).unwrap();
+                                let _ = output.push(AudioMessage::PedalPress);
                             }
                         }
                         _ => ()
@@ -0,0 +1,289 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+/// Real format tag sits at the start of the sub-format GUID
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl SampleFormat {
+    pub fn from_bit_depth(bits: u16) -> Result<Self, String> {
+        match bits {
+            16 => Ok(Self::Int16),
+            24 => Ok(Self::Int24),
+            32 => Ok(Self::Float32),
+            _ => Err(format!("Unsupported bit depth '{bits}', expected 16, 24 or 32")),
+        }
+    }
+
+    fn bits(&self) -> u16 {
+        match self {
+            Self::Int16 => 16,
+            Self::Int24 => 24,
+            Self::Float32 => 32,
+        }
+    }
+
+    fn format_tag(&self) -> u16 {
+        match self {
+            Self::Int16 | Self::Int24 => WAVE_FORMAT_PCM,
+            Self::Float32 => WAVE_FORMAT_IEEE_FLOAT,
+        }
+    }
+}
+
+/// Samples of a read file, interleaved and scaled to -1.0..=1.0
+pub struct WavData {
+    pub channels: u16,
+    pub samples: Vec<f32>,
+    /// Text of a `clm ` chunk, written by wavetable editors as `<!>2048 ...`
+    pub clm: Option<String>,
+    /// Frame length from the `clm ` chunk
+    pub cycle_length: Option<usize>,
+}
+
+/// Reads a RIFF/WAVE file with 8, 16, 24 or 32 bit integer or 32 or 64 bit float samples
+pub fn read(path: &Path) -> Result<WavData, String> {
+    let bytes = std::fs::read(path).map_err(|err| format!("Failed to read '{}': {err}", path.display()))?;
+    read_from(&bytes).map_err(|err| format!("Failed to read '{}': {err}", path.display()))
+}
+
+fn read_from(bytes: &[u8]) -> Result<WavData, String> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(String::from("Not a RIFF/WAVE file"));
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut clm = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let len = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let chunk = &bytes[offset + 8..(offset + 8 + len).min(bytes.len())];
+        match id {
+            b"fmt " if chunk.len() >= 16 => {
+                let mut tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+                if tag == WAVE_FORMAT_EXTENSIBLE && chunk.len() >= 26 {
+                    tag = u16::from_le_bytes([chunk[24], chunk[25]]);
+                }
+                let channels = u16::from_le_bytes([chunk[2], chunk[3]]);
+                let bits = u16::from_le_bytes([chunk[14], chunk[15]]);
+                format = Some((tag, channels, bits));
+            },
+            b"data" => data = Some(chunk),
+            b"clm " => clm = Some(String::from_utf8_lossy(chunk).trim_end_matches('\0').to_string()),
+            _ => {},
+        }
+        // Chunks are padded to an even length
+        offset += 8 + len + (len & 1);
+    }
+
+    let (tag, channels, bits) = format.ok_or("Missing fmt chunk")?;
+    let data = data.ok_or("Missing data chunk")?;
+    if channels == 0 {
+        return Err(String::from("File has no channels"));
+    }
+    let samples = match (tag, bits) {
+        (WAVE_FORMAT_PCM, 8) => data.iter().map(|&x| (x as f32 - 128.0) / 128.0).collect(),
+        (WAVE_FORMAT_PCM, 16) => data.chunks_exact(2).map(|x| i16::from_le_bytes([x[0], x[1]]) as f32 / 32_768.0).collect(),
+        (WAVE_FORMAT_PCM, 24) => data.chunks_exact(3).map(|x| i32::from_le_bytes([0, x[0], x[1], x[2]]) as f32 / 2_147_483_648.0).collect(),
+        (WAVE_FORMAT_PCM, 32) => data.chunks_exact(4).map(|x| i32::from_le_bytes(x.try_into().unwrap()) as f32 / 2_147_483_648.0).collect(),
+        (WAVE_FORMAT_IEEE_FLOAT, 32) => data.chunks_exact(4).map(|x| f32::from_le_bytes(x.try_into().unwrap())).collect(),
+        (WAVE_FORMAT_IEEE_FLOAT, 64) => data.chunks_exact(8).map(|x| f64::from_le_bytes(x.try_into().unwrap()) as f32).collect(),
+        _ => return Err(format!("Unsupported sample format {tag} with {bits} bits")),
+    };
+
+    let cycle_length = clm.as_deref().and_then(parse_cycle_length);
+    Ok(WavData { channels, samples, clm, cycle_length })
+}
+
+fn parse_cycle_length(clm: &str) -> Option<usize> {
+    let digits = clm.strip_prefix("<!>")?;
+    let end = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+    digits[..end].parse().ok().filter(|&length| length > 0)
+}
+
+/// Writes interleaved samples in the range -1.0..=1.0 to a RIFF/WAVE file
+pub fn write(path: &Path, sample_rate: u32, channels: u16, format: SampleFormat, samples: &[f32]) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| format!("Failed to create '{}': {err}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    write_to(&mut writer, sample_rate, channels, format, samples, None)
+        .map_err(|err| format!("Failed to write '{}': {err}", path.display()))
+}
+
+/// Writes mono float frames with a `clm ` chunk so wavetable editors know the frame length
+pub fn write_wavetable(path: &Path, sample_rate: u32, clm: &str, samples: &[f32]) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| format!("Failed to create '{}': {err}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    write_to(&mut writer, sample_rate, 1, SampleFormat::Float32, samples, Some(clm))
+        .map_err(|err| format!("Failed to write '{}': {err}", path.display()))
+}
+
+fn write_to<W: Write>(writer: &mut W, sample_rate: u32, channels: u16, format: SampleFormat, samples: &[f32], clm: Option<&str>) -> std::io::Result<()> {
+    let bytes_per_sample = (format.bits() / 8) as u32;
+    let block_align = channels as u32 * bytes_per_sample;
+    let data_len = samples.len() as u32 * bytes_per_sample;
+    // Float data needs the extended fmt chunk and a fact chunk
+    let is_float = format == SampleFormat::Float32;
+    let fmt_len: u32 = if is_float { 18 } else { 16 };
+    let fact_len: u32 = if is_float { 12 } else { 0 };
+    let clm_len = clm.map_or(0, |clm| clm.len() as u32);
+    let clm_chunk_len = if clm.is_some() { 8 + clm_len + (clm_len & 1) } else { 0 };
+    let riff_len = 4 + (8 + fmt_len) + fact_len + clm_chunk_len + (8 + data_len);
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&riff_len.to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&fmt_len.to_le_bytes())?;
+    writer.write_all(&format.format_tag().to_le_bytes())?;
+    writer.write_all(&channels.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align).to_le_bytes())?;
+    writer.write_all(&(block_align as u16).to_le_bytes())?;
+    writer.write_all(&format.bits().to_le_bytes())?;
+    if is_float {
+        writer.write_all(&0u16.to_le_bytes())?;
+        writer.write_all(b"fact")?;
+        writer.write_all(&4u32.to_le_bytes())?;
+        writer.write_all(&(samples.len() as u32 / channels as u32).to_le_bytes())?;
+    }
+
+    if let Some(clm) = clm {
+        writer.write_all(b"clm ")?;
+        writer.write_all(&clm_len.to_le_bytes())?;
+        writer.write_all(clm.as_bytes())?;
+        if clm_len & 1 == 1 {
+            writer.write_all(&[0])?;
+        }
+    }
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    for &sample in samples {
+        let sample = sample.clamp(-1.0, 1.0);
+        match format {
+            // Rounded, truncating would pull every sample towards zero
+            SampleFormat::Int16 => writer.write_all(&((sample * i16::MAX as f32).round() as i16).to_le_bytes())?,
+            SampleFormat::Int24 => writer.write_all(&((sample * 8_388_607.0).round() as i32).to_le_bytes()[0..3])?,
+            SampleFormat::Float32 => writer.write_all(&sample.to_le_bytes())?,
+        }
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Integer samples of the data chunk, assumes 16 bit mono
+    fn written_samples(samples: &[f32]) -> Vec<i16> {
+        let mut bytes = Vec::new();
+        write_to(&mut bytes, 48000, 1, SampleFormat::Int16, samples, None).unwrap();
+        let data = bytes.windows(4).position(|id| id == b"data").unwrap() + 8;
+        bytes[data..].chunks_exact(2).map(|x| i16::from_le_bytes([x[0], x[1]])).collect()
+    }
+
+    fn round_trip(format: SampleFormat, channels: u16, samples: &[f32]) -> WavData {
+        let mut bytes = Vec::new();
+        write_to(&mut bytes, 48000, channels, format, samples, None).unwrap();
+        read_from(&bytes).unwrap()
+    }
+
+    fn test_samples() -> Vec<f32> {
+        (0..1000).map(|i| (i as f32 * 0.037).sin() * 0.9).chain([1.0, -1.0, 0.0, 1.5, -1.5]).collect()
+    }
+
+    fn assert_close(written: &[f32], read: &[f32], tolerance: f32) {
+        assert_eq!(written.len(), read.len());
+        for (written, read) in written.iter().zip(read) {
+            let expected = written.clamp(-1.0, 1.0);
+            assert!((expected - read).abs() <= tolerance, "wrote {written}, read {read}");
+        }
+    }
+
+    #[test]
+    fn round_trip_16_bit() {
+        let samples = test_samples();
+        let wav = round_trip(SampleFormat::Int16, 2, &samples);
+        assert_eq!(wav.channels, 2);
+        // Written against 32767, read against 32768, so up to half a step of rounding plus one of scale
+        assert_close(&samples, &wav.samples, 1.5 / 32_768.0);
+    }
+
+    #[test]
+    fn round_trip_24_bit() {
+        let samples = test_samples();
+        let wav = round_trip(SampleFormat::Int24, 1, &samples);
+        assert_eq!(wav.channels, 1);
+        assert_close(&samples, &wav.samples, 1.5 / 8_388_608.0);
+    }
+
+    #[test]
+    fn round_trip_32_bit_float() {
+        let samples = test_samples();
+        let wav = round_trip(SampleFormat::Float32, 2, &samples);
+        assert_close(&samples, &wav.samples, 0.0);
+    }
+
+    #[test]
+    fn reads_cycle_length_from_clm_chunk() {
+        let mut bytes = Vec::new();
+        write_to(&mut bytes, 48000, 1, SampleFormat::Float32, &[0.0; 4096], None).unwrap();
+        // Odd length text, the pad byte has to be skipped to find the data chunk
+        let clm = "<!>2048 00000000 odd1";
+        let data = bytes.windows(4).position(|id| id == b"data").unwrap();
+        let mut chunk = b"clm ".to_vec();
+        chunk.extend_from_slice(&(clm.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(clm.as_bytes());
+        chunk.push(0);
+        bytes.splice(data..data, chunk);
+
+        let wav = read_from(&bytes).unwrap();
+        assert_eq!(wav.clm.as_deref(), Some(clm));
+        assert_eq!(wav.cycle_length, Some(2048));
+        assert_eq!(wav.samples.len(), 4096);
+    }
+
+    #[test]
+    fn clm_chunk_round_trips() {
+        // Odd length text needs a pad byte before the data chunk
+        for clm in ["<!>2048 00000000 cav-synth2", "<!>256 00000000 odd"] {
+            let samples = vec![0.25; 512];
+            let mut bytes = Vec::new();
+            write_to(&mut bytes, 48000, 1, SampleFormat::Float32, &samples, Some(clm)).unwrap();
+            let wav = read_from(&bytes).unwrap();
+            assert_eq!(wav.clm.as_deref(), Some(clm));
+            assert_eq!(wav.samples, samples);
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(read_from(b"RIFX\0\0\0\0WAVE").is_err());
+        assert!(read_from(b"RIFF").is_err());
+
+        let mut bytes = Vec::new();
+        write_to(&mut bytes, 48000, 1, SampleFormat::Int16, &[0.5], None).unwrap();
+        let data = bytes.windows(4).position(|id| id == b"data").unwrap();
+        assert!(read_from(&bytes[..data]).is_err());
+    }
+
+    #[test]
+    fn quantization_rounds_to_nearest() {
+        // Just over half a step away from zero, truncation would write 0
+        let half_step = 0.6 / i16::MAX as f32;
+        assert_eq!(written_samples(&[half_step, -half_step]), [1, -1]);
+        assert_eq!(written_samples(&[1.0, -1.0, 1.5, -1.5, 0.0]), [i16::MAX, -i16::MAX, i16::MAX, -i16::MAX, 0]);
+    }
+}